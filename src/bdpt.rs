@@ -0,0 +1,576 @@
+use std::sync::Arc;
+
+use super::camera::{Camera, Film};
+use super::color::Color;
+use super::hittable::{HitRecord, Hittable};
+use super::integrator::{self, Integrator, spectral};
+use super::interior::InteriorStack;
+use super::interval::Interval;
+use super::material::{Material, ScatterRecord};
+use super::ray::Ray;
+use super::rtweekend;
+use super::spectrum;
+use super::vec3::{self, Point3, Vec3};
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+    Camera,
+    // 光源子路径在光源表面上的起点。
+    Light,
+    Surface,
+    Medium,
+}
+
+#[derive(Clone)]
+struct Vertex {
+    kind: VertexKind,
+    p: Point3,
+    // 到达这个顶点的光线，材质的散射和 BSDF 都相对于它计算。
+    r_in: Ray,
+    rec: HitRecord,
+    mat: Option<Arc<dyn Material>>,
+    // 可以继续散射的顶点才有散射记录，只有它们能与另一条子路径相连。
+    srec: Option<ScatterRecord>,
+    // 从子路径的起点到这个顶点的吞吐量。
+    beta: Color,
+    // 镜面反射或折射，不能与另一条子路径相连。
+    delta: bool,
+    // 沿子路径生成这个顶点的概率密度，以及从另一个方向生成它的概率密度，都按面积计。
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl Vertex {
+    fn new(kind: VertexKind, r_in: Ray, rec: HitRecord, beta: Color) -> Self {
+        Self {
+            kind,
+            p: rec.p,
+            r_in,
+            mat: rec.mat.clone(),
+            rec,
+            srec: None,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn on_surface(&self) -> bool {
+        matches!(self.kind, VertexKind::Light | VertexKind::Surface)
+    }
+
+    fn connectable(&self) -> bool {
+        match self.kind {
+            VertexKind::Camera | VertexKind::Light => true,
+            VertexKind::Surface | VertexKind::Medium => !self.delta && self.srec.is_some(),
+        }
+    }
+
+    // 把从这个顶点出发的方向概率密度（按立体角）换算成 next 处按面积的概率密度。
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.p - self.p;
+        let distance_squared = w.length_squared();
+        if distance_squared <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.on_surface() {
+            pdf *= vec3::dot(next.rec.geom_normal, w).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    // 从 prev 到达这个顶点后散射到 next 的概率密度（按 next 处的面积）。
+    // 相机和光源上的顶点与 prev 无关。
+    fn pdf(&self, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.p - self.p;
+        let pdf = match self.kind {
+            VertexKind::Camera => camera.pdf_direction(self.p, direction),
+            VertexKind::Light => self.emission_direction_pdf(direction),
+            VertexKind::Surface | VertexKind::Medium => {
+                let (Some(prev), Some(mat)) = (prev, &self.mat) else {
+                    return 0.0;
+                };
+                let r_in = Ray::new_with_time(prev.p, self.p - prev.p, self.r_in.time())
+                    .with_wavelength(self.r_in.wavelength())
+                    .with_hero_wavelength(self.r_in.hero_wavelength())
+                    .with_interior_priority(self.r_in.interior_priority());
+                let rec = integrator::facing(&self.rec, r_in.direction());
+                let mut srec = ScatterRecord::default();
+                if !mat.scatter(&r_in, &rec, &mut srec) || srec.skip_pdf {
+                    return 0.0;
+                }
+                srec.pdf.value(direction)
+            }
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // 这个顶点作为光源向 direction 发出的辐亮度。
+    fn emitted_toward(&self, direction: Vec3) -> Color {
+        integrator::emitted_toward(&self.rec, &self.r_in, direction)
+    }
+
+    fn outward_normal(&self) -> Vec3 {
+        integrator::outward_normal(&self.rec)
+    }
+
+    fn emission_direction_pdf(&self, direction: Vec3) -> f64 {
+        integrator::emission_direction_pdf(&self.rec, &self.r_in, direction)
+    }
+}
+
+// 双向路径追踪：分别从相机和光源出发生成两条子路径，把它们的顶点两两相连，
+// 所有连接方式用多重重要性采样（幂启发式）结合。光源子路径直接连接到相机的贡献
+// 累加到胶片上，这种方式能找到透过玻璃看到的焦散。
+// 每类反弹的次数上限只对路径追踪有效，这里只限制路径的总长度。多重重要性采样的权重
+// 不考虑介质中距离采样的概率，嵌套物体的吸收也只作用在两条子路径上，不作用在连接上。
+pub struct Bdpt;
+
+impl Integrator for Bdpt {
+    fn ray_color(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> Color {
+        let hero = r.hero_wavelength();
+        // 路径上最多有 max_depth 个相机之外的顶点，与路径追踪的深度一致。
+        let max_vertices = camera.max_depth.max(0) as usize;
+
+        let mut camera_path = vec![Vertex::new(
+            VertexKind::Camera,
+            *r,
+            HitRecord {
+                p: r.origin(),
+                ..Default::default()
+            },
+            Color::one(),
+        )];
+        let pdf_dir = camera.pdf_direction(r.origin(), r.direction());
+        let mut radiance = random_walk(
+            camera,
+            world,
+            *r,
+            Color::one(),
+            pdf_dir,
+            max_vertices + 1,
+            &mut camera_path,
+        );
+
+        let mut light_path = Vec::new();
+        if let Some(lights) = lights {
+            light_subpath(camera, world, lights, r, max_vertices, &mut light_path);
+        }
+
+        // 解析光源只能通过阴影光线照亮物体，只有这一种方式。
+        for z in camera_path
+            .iter()
+            .skip(1)
+            .take(max_vertices.saturating_sub(1))
+        {
+            if let (Some(mat), Some(srec)) = (&z.mat, &z.srec) {
+                if !z.delta {
+                    for light in camera.analytic_lights.iter() {
+                        radiance += z.beta
+                            * integrator::analytic_light(
+                                &z.r_in,
+                                &z.rec,
+                                mat,
+                                srec,
+                                light.as_ref(),
+                                world,
+                            );
+                    }
+                }
+            }
+        }
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                if s + t < 2 || s + t - 1 > max_vertices {
+                    continue;
+                }
+                if t == 1 {
+                    let Some((sampled, i, j, contribution)) =
+                        connect_to_camera(camera, world, &light_path[..s])
+                    else {
+                        continue;
+                    };
+                    let weight = mis_weight(camera, lights, &light_path[..s], &[sampled], s, t);
+                    film.add_splat(i, j, spectrum::to_linear_srgb(weight * contribution, hero));
+                    continue;
+                }
+
+                let contribution = connect(world, &light_path[..s], &camera_path[..t]);
+                if contribution.near_zero() {
+                    continue;
+                }
+                radiance +=
+                    mis_weight(camera, lights, &light_path[..s], &camera_path, s, t) * contribution;
+            }
+        }
+
+        spectrum::to_linear_srgb(radiance, hero)
+    }
+}
+
+// 沿 ray 生成子路径的其余顶点，直到 path 中有 max_vertices 个顶点。pdf_dir 是生成 ray 的
+// 方向概率密度，beta 是 ray 携带的吞吐量。返回相机子路径离开场景时看到的背景。
+fn random_walk(
+    camera: &Camera,
+    world: &Arc<dyn Hittable>,
+    mut ray: Ray,
+    mut beta: Color,
+    mut pdf_dir: f64,
+    max_vertices: usize,
+    path: &mut Vec<Vertex>,
+) -> Color {
+    // 与路径追踪相同，穿过虚假的交点时只把求交的起点向前移动。
+    let mut t_start = 0.0;
+    let mut interior = InteriorStack::default();
+    while path.len() < max_vertices {
+        let mut rec = HitRecord::default();
+        let ray_t = Interval::new(t_start + 0.001, rtweekend::INFINITY);
+        let hit = world.hit(&ray, &ray_t, &mut rec);
+        beta = beta * integrator::free_flight_weight(camera, &ray, &ray_t, hit.then_some(rec.t));
+        if !hit {
+            // 背景只能由相机子路径看到，不需要多重重要性采样的权重。
+            if path[0].kind == VertexKind::Camera {
+                return beta * spectral(camera.background_value(ray.direction()), &ray);
+            }
+            break;
+        }
+        let Some(mat) = rec.mat.clone() else {
+            break;
+        };
+
+        beta = beta * interior.absorption(&ray, t_start, &rec);
+        if let Some(next) = interior.advance(&ray, &mut rec) {
+            ray = next;
+            t_start = rec.t;
+            continue;
+        }
+
+        let mut srec = ScatterRecord::default();
+        let scatters = mat.scatter(&ray, &rec, &mut srec);
+
+        let kind = if scatters && srec.volume {
+            VertexKind::Medium
+        } else {
+            VertexKind::Surface
+        };
+        let mut vertex = Vertex::new(kind, ray, rec.clone(), beta);
+        vertex.pdf_fwd = path.last().unwrap().convert_density(pdf_dir, &vertex);
+        if !scatters {
+            path.push(vertex);
+            break;
+        }
+        if path.len() + 1 >= max_vertices {
+            vertex.srec = Some(srec);
+            path.push(vertex);
+            break;
+        }
+
+        let scattered = if srec.skip_pdf {
+            let mut skip_pdf_ray = srec.skip_pdf_ray;
+            beta = beta * srec.attenuation;
+            if skip_pdf_ray.wavelength() == 0.0 {
+                skip_pdf_ray = skip_pdf_ray.with_wavelength(ray.wavelength());
+            } else if ray.wavelength() == 0.0 {
+                beta = spectrum::terminate_secondary(beta, ray.hero_wavelength());
+            }
+            vertex.delta = true;
+            pdf_dir = 0.0;
+            path.last_mut().unwrap().pdf_rev = 0.0;
+            skip_pdf_ray
+        } else {
+            let scattered = Ray::new_with_time(rec.p, srec.pdf.generate(), ray.time())
+                .with_wavelength(ray.wavelength());
+            let pdf = srec.pdf.value(scattered.direction());
+            let bsdf_cos = mat.bsdf_cos(&ray, &rec, &srec, &scattered);
+            if pdf <= 0.0 || bsdf_cos.near_zero() {
+                vertex.srec = Some(srec);
+                path.push(vertex);
+                break;
+            }
+            beta = beta * bsdf_cos / pdf;
+
+            // 反方向的概率密度：从下一个顶点到达这里之后散射回上一个顶点。
+            let reversed = Ray::new_with_time(
+                rec.p + scattered.direction(),
+                -scattered.direction(),
+                ray.time(),
+            )
+            .with_wavelength(ray.wavelength())
+            .with_hero_wavelength(ray.hero_wavelength())
+            .with_interior_priority(interior.priority());
+            let reversed_rec = integrator::facing(&rec, reversed.direction());
+            let mut reversed_srec = ScatterRecord::default();
+            let pdf_rev = if mat.scatter(&reversed, &reversed_rec, &mut reversed_srec)
+                && !reversed_srec.skip_pdf
+            {
+                reversed_srec.pdf.value(-ray.direction())
+            } else {
+                0.0
+            };
+            let prev = path.last_mut().unwrap();
+            prev.pdf_rev = vertex.convert_density(pdf_rev, prev);
+            pdf_dir = pdf;
+            scattered
+        };
+        vertex.srec = Some(srec);
+
+        path.push(vertex);
+        ray = interior
+            .follow(&rec, scattered)
+            .with_hero_wavelength(ray.hero_wavelength());
+        t_start = 0.0;
+
+        // 与路径追踪相同的俄罗斯轮盘赌。
+        if path.len() as i32 > camera.rr_depth {
+            let survive = beta.x().max(beta.y()).max(beta.z()).min(1.0);
+            if rtweekend::random_double() >= survive {
+                break;
+            }
+            beta /= survive;
+        }
+    }
+    Color::default()
+}
+
+// 在光源上取一点，按余弦分布发射一条光线，生成光源子路径。
+fn light_subpath(
+    camera: &Camera,
+    world: &Arc<dyn Hittable>,
+    lights: &Arc<dyn Hittable>,
+    r: &Ray,
+    max_vertices: usize,
+    path: &mut Vec<Vertex>,
+) {
+    let mut rec = HitRecord::default();
+    let pdf_pos = lights.sample_emission(&mut rec);
+    if pdf_pos <= 0.0 || max_vertices == 0 {
+        return;
+    }
+    // 起点的吞吐量只包括位置的概率密度，发射的辐亮度在连接时按方向计算。
+    let r_in = Ray::new_with_time(rec.p, -rec.geom_normal, r.time())
+        .with_hero_wavelength(r.hero_wavelength());
+    let mut y0 = Vertex::new(VertexKind::Light, r_in, rec, Color::one() / pdf_pos);
+    y0.pdf_fwd = pdf_pos;
+
+    let direction = integrator::sample_emission_direction(&y0.rec, &y0.r_in);
+    let pdf_dir = y0.emission_direction_pdf(direction);
+    let emitted = y0.emitted_toward(direction);
+    if pdf_dir <= 0.0 || emitted.near_zero() {
+        path.push(y0);
+        return;
+    }
+    let cos_theta = vec3::dot(direction, y0.outward_normal()).abs();
+    let beta = emitted * cos_theta / (pdf_pos * pdf_dir);
+
+    let ray =
+        Ray::new_with_time(y0.p, direction, r.time()).with_hero_wavelength(r.hero_wavelength());
+    path.push(y0);
+    random_walk(camera, world, ray, beta, pdf_dir, max_vertices, path);
+}
+
+// 从 a 到 b 之间没有遮挡时返回沿途参与介质的透射率。
+fn visibility(world: &Arc<dyn Hittable>, a: &Vertex, b: Point3) -> Option<Color> {
+    let to_b = b - a.p;
+    let distance = to_b.length();
+    let ray = Ray::new_with_time(a.p, to_b / distance, a.r_in.time())
+        .with_wavelength(a.r_in.wavelength())
+        .with_hero_wavelength(a.r_in.hero_wavelength())
+        .with_interior_priority(a.r_in.interior_priority());
+    let ray_t = Interval::new(0.001, distance - 0.001);
+    let mut rec = HitRecord::default();
+    if world.shadow_hit(&ray, &ray_t, &mut rec) {
+        return None;
+    }
+    Some(world.transmittance(&ray, &ray_t))
+}
+
+// 顶点 v 沿 v 指向 target 的方向散射的 BSDF 乘以余弦项。
+fn bsdf_cos_toward(v: &Vertex, target: Point3) -> Color {
+    let (Some(mat), Some(srec)) = (&v.mat, &v.srec) else {
+        return Color::default();
+    };
+    let scattered =
+        Ray::new_with_time(v.p, target - v.p, v.r_in.time()).with_wavelength(v.r_in.wavelength());
+    mat.bsdf_cos(&v.r_in, &v.rec, srec, &scattered)
+}
+
+// 光源子路径的前 s 个顶点与相机子路径的前 t 个顶点（t >= 2）相连得到的贡献，不含权重。
+fn connect(world: &Arc<dyn Hittable>, light_path: &[Vertex], camera_path: &[Vertex]) -> Color {
+    let pt = camera_path.last().unwrap();
+    let Some(qs) = light_path.last() else {
+        // 相机子路径直接命中了光源。
+        let Some(mat) = &pt.mat else {
+            return Color::default();
+        };
+        return pt.beta * mat.emitted(&pt.r_in, &pt.rec, pt.rec.u, pt.rec.v, pt.p);
+    };
+    if !pt.connectable() || !qs.connectable() {
+        return Color::default();
+    }
+
+    let f_camera = bsdf_cos_toward(pt, qs.p);
+    if f_camera.near_zero() {
+        return Color::default();
+    }
+    let distance_squared = (qs.p - pt.p).length_squared();
+    let f_light = if qs.kind == VertexKind::Light {
+        let direction = pt.p - qs.p;
+        let cos_theta = vec3::dot(vec3::unit_vector(direction), qs.outward_normal()).abs();
+        qs.emitted_toward(direction) * cos_theta
+    } else {
+        bsdf_cos_toward(qs, pt.p)
+    };
+    if f_light.near_zero() {
+        return Color::default();
+    }
+    let Some(transmittance) = visibility(world, pt, qs.p) else {
+        return Color::default();
+    };
+    qs.beta
+        * f_light
+        * f_camera
+        * pt.beta
+        * transmittance
+        * integrator::wavelength_factor(&pt.r_in, &qs.r_in)
+        / distance_squared
+}
+
+// 光源子路径的最后一个顶点直接连接到镜头上的一点。返回镜头上的顶点、像素和贡献（不含权重）。
+fn connect_to_camera(
+    camera: &Camera,
+    world: &Arc<dyn Hittable>,
+    light_path: &[Vertex],
+) -> Option<(Vertex, u32, u32, Color)> {
+    let qs = light_path.last()?;
+    if !qs.connectable() {
+        return None;
+    }
+    let sample = camera.sample_lens(qs.p)?;
+    let f = if qs.kind == VertexKind::Light {
+        let direction = sample.origin - qs.p;
+        let cos_theta = vec3::dot(vec3::unit_vector(direction), qs.outward_normal()).abs();
+        qs.emitted_toward(direction) * cos_theta
+    } else {
+        bsdf_cos_toward(qs, sample.origin)
+    };
+    if f.near_zero() {
+        return None;
+    }
+    let transmittance = visibility(world, qs, sample.origin)?;
+
+    let r_in = Ray::new_with_time(sample.origin, qs.p - sample.origin, qs.r_in.time())
+        .with_hero_wavelength(qs.r_in.hero_wavelength());
+    let vertex = Vertex::new(
+        VertexKind::Camera,
+        r_in,
+        HitRecord {
+            p: sample.origin,
+            ..Default::default()
+        },
+        Color::one() * sample.importance,
+    );
+    let contribution = qs.beta * f * transmittance * sample.importance;
+    Some((vertex, sample.i, sample.j, contribution))
+}
+
+// 第 s、t 种连接方式的权重。把路径上每个顶点的正反两个方向的概率密度之比依次相乘，
+// 得到其余连接方式与这一种的概率密度之比，再按幂启发式归一化。镜面顶点的概率密度记为 0，
+// 不能参与连接，比值中把 0 换成 1。camera_path 的前 t 个顶点有效；t = 1 时是镜头上的采样点。
+fn mis_weight(
+    camera: &Camera,
+    lights: &Option<Arc<dyn Hittable>>,
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    s: usize,
+    t: usize,
+) -> f64 {
+    let pt = &camera_path[t - 1];
+    let pt_minus = if t > 1 {
+        Some(&camera_path[t - 2])
+    } else {
+        None
+    };
+    let qs = if s > 0 {
+        Some(&light_path[s - 1])
+    } else {
+        None
+    };
+    let qs_minus = if s > 1 {
+        Some(&light_path[s - 2])
+    } else {
+        None
+    };
+
+    // 连接处的两个端点和它们的前一个顶点在另一个方向上的概率密度。
+    let pt_rev = match qs {
+        Some(qs) => qs.pdf(camera, qs_minus, pt),
+        None => {
+            // 不在光源列表中的发光物体不能由光源子路径生成，直接命中是唯一的方式。
+            let origin_pdf = lights
+                .as_ref()
+                .map_or(0.0, |lights| lights.emission_pdf(&pt.r_in, &pt.rec));
+            if origin_pdf <= 0.0 {
+                return 1.0;
+            }
+            origin_pdf
+        }
+    };
+    let pt_minus_rev = pt_minus.map(|pt_minus| match qs {
+        Some(qs) => pt.pdf(camera, Some(qs), pt_minus),
+        None => {
+            let mut light = pt.clone();
+            light.kind = VertexKind::Light;
+            light.pdf(camera, None, pt_minus)
+        }
+    });
+    let qs_rev = qs.map(|qs| pt.pdf(camera, pt_minus, qs));
+    let qs_minus_rev = qs_minus.map(|qs_minus| qs.unwrap().pdf(camera, Some(pt), qs_minus));
+
+    let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+    let mut sum = 0.0;
+
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+        let pdf_rev = if i == t - 1 {
+            pt_rev
+        } else if i + 2 == t {
+            pt_minus_rev.unwrap()
+        } else {
+            camera_path[i].pdf_rev
+        };
+        ratio *= remap(pdf_rev) / remap(camera_path[i].pdf_fwd);
+        let delta = i != t - 1 && camera_path[i].delta;
+        if !delta && !camera_path[i - 1].delta {
+            sum += ratio * ratio;
+        }
+    }
+
+    let mut ratio = 1.0;
+    for i in (0..s).rev() {
+        let pdf_rev = if i == s - 1 {
+            qs_rev.unwrap()
+        } else if i + 2 == s {
+            qs_minus_rev.unwrap()
+        } else {
+            light_path[i].pdf_rev
+        };
+        ratio *= remap(pdf_rev) / remap(light_path[i].pdf_fwd);
+        let delta = i != s - 1 && light_path[i].delta;
+        let delta_prev = i > 0 && light_path[i - 1].delta;
+        if !delta && !delta_prev {
+            sum += ratio * ratio;
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
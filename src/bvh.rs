@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use super::aabb::Aabb;
+use super::color::Color;
 use super::hittable::{HitRecord, Hittable};
 use super::hittable_list::HittableList;
 use super::interval::Interval;
@@ -113,4 +114,54 @@ impl Hittable for BvhNode {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        self.left.collect_lights(lights);
+        // 只有一个物体的节点左右子树相同。
+        if !Arc::ptr_eq(&self.left, &self.right) {
+            self.right.collect_lights(lights);
+        }
+    }
+
+    fn collect_media(&self, media: &mut HittableList) {
+        self.left.collect_media(media);
+        if !Arc::ptr_eq(&self.left, &self.right) {
+            self.right.collect_media(media);
+        }
+    }
+
+    fn power(&self) -> f64 {
+        if Arc::ptr_eq(&self.left, &self.right) {
+            self.left.power()
+        } else {
+            self.left.power() + self.right.power()
+        }
+    }
+
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let mut ray_t = ray_t.clone();
+        if !self.bbox.hit(r, &mut ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.shadow_hit(r, &ray_t, rec);
+        let ray_t = Interval::new(ray_t.min, if hit_left { rec.t } else { ray_t.max });
+        let hit_right = self.right.shadow_hit(r, &ray_t, rec);
+
+        hit_left || hit_right
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        let mut ray_t = ray_t.clone();
+        if !self.bbox.hit(r, &mut ray_t) {
+            return Color::one();
+        }
+
+        let left = self.left.transmittance(r, &ray_t);
+        if Arc::ptr_eq(&self.left, &self.right) {
+            left
+        } else {
+            left * self.right.transmittance(r, &ray_t)
+        }
+    }
 }
@@ -3,29 +3,90 @@ use image::{ImageBuffer, RgbImage};
 use indicatif::ProgressBar;
 
 use rayon::prelude::*;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use super::color::Color;
-use super::hittable::{HitRecord, Hittable};
-use super::interval::Interval;
-use super::material;
-use super::pdf;
-use super::pdf::{HittablePdf, Pdf};
+use super::environment::{Environment, EnvironmentLight};
+use super::hittable::Hittable;
+use super::hittable_list::HittableList;
+use super::integrator::{Integrator, PathTracer};
+use super::light::{AnalyticLight, LightBvh, LightSampler};
 use super::ray::Ray;
 use super::rtweekend;
+use super::spectrum;
 use super::vec3::{self, Point3, Vec3};
+
+// 光源子路径直接连接到相机时，贡献落在投影所在的像素上，与像素自己的样本分开累加。
+pub struct Film {
+    width: u32,
+    height: u32,
+    splats: Vec<Mutex<Color>>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            splats: (0..width * height)
+                .map(|_| Mutex::new(Color::default()))
+                .collect(),
+        }
+    }
+
+    // 每个相机样本各对应一条光源子路径，除以像素数之后与相机样本按同样的方式平均。
+    pub fn add_splat(&self, i: u32, j: u32, c: Color) {
+        let index = (j * self.width + i) as usize;
+        *self.splats[index].lock().unwrap() += c / (self.width * self.height) as f64;
+    }
+
+    fn splat(&self, i: u32, j: u32) -> Color {
+        *self.splats[(j * self.width + i) as usize].lock().unwrap()
+    }
+}
+
+// 从场景中的一点连接到镜头的采样结果。
+pub struct LensSample {
+    // 镜头上的点和这一点投影到的像素。
+    pub origin: Point3,
+    pub i: u32,
+    pub j: u32,
+    // 相机的重要性除以镜头上取点的概率密度，包括两端之间的几何项中相机一侧的余弦和距离的平方。
+    pub importance: f64,
+}
+
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: u32,
     pub samples_per_pixel: usize,
+    // 路径的最大总反弹次数。另外每一类反弹有各自的上限，默认不单独限制。
     pub max_depth: i32,
+    pub max_diffuse_depth: i32,
+    pub max_specular_depth: i32,
+    pub max_transmission_depth: i32,
+    pub max_volume_depth: i32,
+    // 反弹次数达到 rr_depth 之后按吞吐量进行俄罗斯轮盘赌终止。
+    pub rr_depth: i32,
     pub background: Color,
+    // 环境光（例如 HDR 环境贴图），设置后代替 background 并参与光源采样。
+    pub environment: Option<Arc<dyn Environment>>,
+    // 点光源、聚光灯和平行光。它们不在场景中，只通过阴影光线照亮物体。
+    pub analytic_lights: Vec<Arc<dyn AnalyticLight>>,
     pub vfov: f64,
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    // 光源很多（例如发光的网格）时，用光源的空间层次结构代替按功率选择的光源列表。
+    pub light_bvh: bool,
+    // 谱渲染：每条相机光线采样一个主波长，RGB 的反射率、纹理和光源上采样为光谱，
+    // 在胶片上按配色函数转换回 sRGB。
+    pub spectral: bool,
+    // 由相机光线估计辐亮度的方法，默认为单向路径追踪。
+    pub integrator: Arc<dyn Integrator>,
+    // 渲染开始时从场景中收集的有色介质，积分器为光线在其中没有碰撞的每一段计算权重。
+    pub chromatic_media: HittableList,
     image_height: u32,
     sqrt_spp: usize,
     recip_sqrt_spp: f64,
@@ -41,8 +102,34 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn render(&mut self, world: Arc<dyn Hittable>, lights: Arc<dyn Hittable>) {
+    pub fn render(&mut self, world: Arc<dyn Hittable>) {
+        // 从场景中找出所有发光的图元作为光源采样的目标。
+        let mut lights = HittableList::default();
+        world.collect_lights(&mut lights);
+        if let Some(environment) = &self.environment {
+            lights.add(Arc::new(EnvironmentLight::new(
+                Arc::clone(environment),
+                world.bounding_box(),
+            )));
+        }
+        if lights.objects.is_empty() {
+            self.render_scene(world, None);
+        } else if self.light_bvh {
+            self.render_scene(world, Some(Arc::new(LightBvh::new(lights))));
+        } else {
+            self.render_scene(world, Some(Arc::new(LightSampler::new(lights))));
+        }
+    }
+
+    pub fn render_with_lights(&mut self, world: Arc<dyn Hittable>, lights: Arc<dyn Hittable>) {
+        // 手动指定用于光源采样的物体，覆盖自动收集的光源列表。
+        self.render_scene(world, Some(lights));
+    }
+
+    fn render_scene(&mut self, world: Arc<dyn Hittable>, lights: Option<Arc<dyn Hittable>>) {
         self.initialize();
+        self.chromatic_media = HittableList::default();
+        world.collect_media(&mut self.chromatic_media);
 
         let path = std::path::Path::new("output/work/image9.png");
         let prefix = path.parent().unwrap();
@@ -50,12 +137,36 @@ impl Camera {
 
         let mut img: RgbImage = ImageBuffer::new(self.image_width, self.image_height);
 
-        let progress = if option_env!("CI").unwrap_or_default() == "true" {
-            ProgressBar::hidden()
-        } else {
-            ProgressBar::new((self.image_height * self.image_width) as u64)
+        let film = Film::new(self.image_width, self.image_height);
+        let pixels = match self.integrator.render(self, &world, &lights) {
+            Some(pixels) => pixels,
+            None => self.render_pixels(&world, &lights, &film),
         };
 
+        for j in 0..self.image_height {
+            for i in 0..self.image_width {
+                let index = (j * self.image_width + i) as usize;
+                let pixel = img.get_pixel_mut(i, j);
+                *pixel = (pixels[index] + film.splat(i, j)).to_color(self.samples_per_pixel);
+            }
+        }
+
+        println!(
+            "Output image as \"{}\"",
+            style(path.to_str().unwrap()).yellow()
+        );
+        img.save(path).expect("Cannot save the image to the file");
+    }
+
+    // 逐个像素调用积分器，返回每个像素的样本之和。
+    fn render_pixels(
+        &self,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> Vec<Color> {
+        let progress = self.progress_bar((self.image_height * self.image_width) as u64);
+
         let pixel_coords: Vec<(u32, u32)> = (0..self.image_height)
             .flat_map(|j| (0..self.image_width).map(move |i| (i, j)))
             .collect();
@@ -63,15 +174,20 @@ impl Camera {
         let pixels: Vec<Color> = pixel_coords
             .into_par_iter()
             .map(|(i, j)| {
-                let thread_world = Arc::clone(&world);
-                let thread_lights = Arc::clone(&lights);
+                let thread_world = Arc::clone(world);
+                let thread_lights = lights.clone();
 
                 let mut pixel_color = Color::default();
                 for s_j in 0..self.sqrt_spp {
                     for s_i in 0..self.sqrt_spp {
                         let r = self.get_ray(i, j, s_i as u32, s_j as u32);
-                        pixel_color +=
-                            self.ray_color(&r, self.max_depth, &thread_world, &thread_lights);
+                        pixel_color += self.integrator.ray_color(
+                            self,
+                            &r,
+                            &thread_world,
+                            &thread_lights,
+                            film,
+                        );
                     }
                 }
                 progress.inc(1);
@@ -80,20 +196,15 @@ impl Camera {
             .collect();
 
         progress.finish();
+        pixels
+    }
 
-        for j in 0..self.image_height {
-            for i in 0..self.image_width {
-                let index = (j * self.image_width + i) as usize;
-                let pixel = img.get_pixel_mut(i, j);
-                *pixel = pixels[index].to_color(self.samples_per_pixel);
-            }
+    pub fn progress_bar(&self, len: u64) -> ProgressBar {
+        if option_env!("CI").unwrap_or_default() == "true" {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(len)
         }
-
-        println!(
-            "Output image as \"{}\"",
-            style(path.to_str().unwrap()).yellow()
-        );
-        img.save(path).expect("Cannot save the image to the file");
     }
 
     fn initialize(&mut self) {
@@ -134,58 +245,104 @@ impl Camera {
             self.focus_dist * rtweekend::degrees_to_radians(self.defocus_angle / 2.0).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
+
+        if self.spectral {
+            spectrum::initialize();
+        }
     }
 
-    fn ray_color(
-        &self,
-        r: &Ray,
-        depth: i32,
-        world: &Arc<dyn Hittable>,
-        lights: &Arc<dyn Hittable>,
-    ) -> Color {
-        let mut rec = HitRecord::default();
+    pub fn background_value(&self, direction: Vec3) -> Color {
+        match &self.environment {
+            Some(environment) => environment.value(direction),
+            None => self.background,
+        }
+    }
 
-        if depth <= 0 {
-            return Color::default();
+    // 从镜头上的 origin 沿 direction 的光线在胶片上的位置，以像素为单位，超出画面时为 None。
+    fn raster(&self, origin: Point3, direction: Vec3) -> Option<(f64, f64)> {
+        let cos_theta = -vec3::dot(direction, self.w);
+        if cos_theta <= 0.0 {
+            return None;
         }
+        let focus_point = origin + direction * (self.focus_dist / cos_theta);
+        let upper_left = self.pixel00_loc - 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let offset = focus_point - upper_left;
+        let x = vec3::dot(offset, self.pixel_delta_u) / self.pixel_delta_u.length_squared();
+        let y = vec3::dot(offset, self.pixel_delta_v) / self.pixel_delta_v.length_squared();
+        if x < 0.0 || y < 0.0 || x >= self.image_width as f64 || y >= self.image_height as f64 {
+            return None;
+        }
+        Some((x, y))
+    }
 
-        if !world.hit(r, &Interval::new(0.001, rtweekend::INFINITY), &mut rec) {
-            return self.background;
+    // 相机光线在对焦平面上均匀地分布在像素内，换算成方向的概率密度（按立体角）。
+    // 这里按整个画面计算，与每个相机样本对应一条光源子路径的约定一致。
+    pub fn pdf_direction(&self, origin: Point3, direction: Vec3) -> f64 {
+        let direction = vec3::unit_vector(direction);
+        if self.raster(origin, direction).is_none() {
+            return 0.0;
         }
-        if let Some(mat) = rec.mat.clone() {
-            let mut srec = material::ScatterRecord::default();
-            let color_from_emission = mat.emitted(r, &rec, rec.u, rec.v, rec.p);
-            if !mat.scatter(r, &rec, &mut srec) {
-                return color_from_emission;
-            }
-            if srec.skip_pdf {
-                return srec.attenuation
-                    * self.ray_color(&srec.skip_pdf_ray, depth - 1, world, lights);
-            }
-            let light_pdf = HittablePdf::new(Arc::clone(lights), rec.p);
-            let mixed_pdf = pdf::MixturePdf::new(light_pdf, Arc::clone(&srec.pdf));
+        let cos_theta = -vec3::dot(direction, self.w);
+        let film_area = (self.image_width * self.image_height) as f64
+            * self.pixel_delta_u.length()
+            * self.pixel_delta_v.length();
+        self.focus_dist * self.focus_dist / (film_area * cos_theta.powi(3))
+    }
 
-            let scattered = Ray::new_with_time(rec.p, mixed_pdf.generate(), r.time());
-            let pdf = mixed_pdf.value(scattered.direction());
+    // 在镜头上取一点与场景中的点 p 相连，用于光源子路径直接连接到相机。
+    pub fn sample_lens(&self, p: Point3) -> Option<LensSample> {
+        let origin = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample()
+        };
+        let to_p = p - origin;
+        let distance_squared = to_p.length_squared();
+        let direction = to_p / distance_squared.sqrt();
+        let (x, y) = self.raster(origin, direction)?;
+
+        let cos_theta = -vec3::dot(direction, self.w);
+        let pixel_area = self.pixel_delta_u.length() * self.pixel_delta_v.length();
+        Some(LensSample {
+            origin,
+            i: x as u32,
+            j: y as u32,
+            importance: self.focus_dist * self.focus_dist
+                / (pixel_area * cos_theta.powi(3) * distance_squared),
+        })
+    }
 
-            let scattering_pdf = mat.scattering_pdf(r, &rec, &scattered);
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
 
-            let color_from_scatter = (srec.attenuation
-                * scattering_pdf
-                * self.ray_color(&scattered, depth - 1, world, lights))
-                / pdf;
+    // 像素 (i, j) 的第 sample 个样本的相机光线，样本按分层抖动分布在像素内。
+    pub fn sample_ray(&self, i: u32, j: u32, sample: usize) -> Ray {
+        let s_i = sample % self.sqrt_spp;
+        let s_j = sample / self.sqrt_spp % self.sqrt_spp;
+        self.get_ray(i, j, s_i as u32, s_j as u32)
+    }
 
-            color_from_emission + color_from_scatter
-        } else {
-            Color::default()
-        }
+    // 对焦平面上一个像素的宽度换算到 p 所在的深度，用来估计一个像素对应的场景中的尺寸。
+    pub fn pixel_footprint(&self, p: Point3) -> f64 {
+        let depth = -vec3::dot(p - self.center, self.w);
+        self.pixel_delta_u.length() * depth.max(0.0) / self.focus_dist
+    }
+
+    // 穿过图像上连续坐标 (x, y) 的相机光线，x 在 [0, image_width) 中，y 在 [0, image_height) 中。
+    pub fn raster_ray(&self, x: f64, y: f64) -> Ray {
+        let pixel_sample =
+            self.pixel00_loc + (x - 0.5) * self.pixel_delta_u + (y - 0.5) * self.pixel_delta_v;
+        self.ray_through(pixel_sample)
     }
 
     fn get_ray(&self, i: u32, j: u32, s_i: u32, s_j: u32) -> Ray {
         let pixel_center =
             self.pixel00_loc + i as f64 * self.pixel_delta_u + j as f64 * self.pixel_delta_v;
-        let pixel_sample = pixel_center + self.pixel_sample_square(s_i, s_j);
+        self.ray_through(pixel_center + self.pixel_sample_square(s_i, s_j))
+    }
 
+    fn ray_through(&self, pixel_sample: Point3) -> Ray {
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
@@ -194,7 +351,12 @@ impl Camera {
         let ray_direction = pixel_sample - ray_origin;
         let ray_time = rtweekend::random_double();
 
-        Ray::new_with_time(ray_origin, ray_direction, ray_time)
+        let ray = Ray::new_with_time(ray_origin, ray_direction, ray_time);
+        if self.spectral {
+            ray.with_hero_wavelength(spectrum::sample_wavelength())
+        } else {
+            ray
+        }
     }
 
     fn pixel_sample_square(&self, s_i: u32, s_j: u32) -> Vec3 {
@@ -217,13 +379,24 @@ impl Default for Camera {
             image_height: 0,
             samples_per_pixel: 10,
             max_depth: 10,
+            max_diffuse_depth: i32::MAX,
+            max_specular_depth: i32::MAX,
+            max_transmission_depth: i32::MAX,
+            max_volume_depth: i32::MAX,
+            rr_depth: 3,
             background: Color::default(),
+            environment: None,
+            analytic_lights: Vec::new(),
             vfov: 90.0,
             lookfrom: Point3::new(0.0, 0.0, -1.0),
             lookat: Point3::new(0.0, 0.0, 0.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0,
             focus_dist: 10.0,
+            light_bvh: false,
+            spectral: false,
+            integrator: Arc::new(PathTracer),
+            chromatic_media: HittableList::default(),
             sqrt_spp: 10.0_f64.sqrt() as usize,
             recip_sqrt_spp: 1.0 / (10.0_f64.sqrt()),
             center: Point3::default(),
@@ -17,6 +17,61 @@ pub fn linear_to_gamma(liner_component: f64) -> f64 {
     }
 }
 
+pub fn srgb_to_linear(srgb_component: f64) -> f64 {
+    if srgb_component <= 0.04045 {
+        srgb_component / 12.92
+    } else {
+        ((srgb_component + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// 线性 sRGB 颜色的亮度。
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+// CIE 1931 配色函数（Wyman 等人的多瓣高斯拟合），lambda 以纳米为单位。
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let lobe = |mu: f64, sigma1: f64, sigma2: f64| {
+        let sigma = if lambda < mu { sigma1 } else { sigma2 };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    Vec3::new(
+        1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7)
+            - 0.065 * lobe(501.1, 20.4, 26.2),
+        0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    )
+}
+
+// 温度为 kelvin 的黑体辐射的颜色（线性 sRGB），亮度归一化为 1。
+// 普朗克定律在可见光范围内对 CIE 1931 配色函数积分。
+pub fn blackbody(kelvin: f64) -> Color {
+    let mut xyz = Vec3::zero();
+    for step in 0..=80 {
+        let lambda = 380.0 + 5.0 * step as f64;
+        let meters = lambda * 1e-9;
+        let radiance = 1.0 / (meters.powi(5) * ((1.4388e-2 / (meters * kelvin)).exp() - 1.0));
+        xyz += radiance * cie_xyz(lambda);
+    }
+    xyz_to_linear_srgb(xyz / xyz.y())
+}
+
+// CIE XYZ 转换到线性 sRGB（D65 白点），色域之外的负值截断为零。
+pub fn xyz_to_linear_srgb(xyz: Vec3) -> Color {
+    let c = xyz_to_linear_srgb_unclamped(xyz);
+    Color::new(c.x().max(0.0), c.y().max(0.0), c.z().max(0.0))
+}
+
+// 不截断负值的版本，用于还要累加的样本，截断留到最后。
+pub fn xyz_to_linear_srgb_unclamped(xyz: Vec3) -> Color {
+    Color::new(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
+
 impl Color {
     //pub fn to_u64(self) -> (u64, u64, u64) {
     //    let x = (self.x * 255.999) as u64;
@@ -3,24 +3,34 @@ use std::sync::Arc;
 use super::aabb::Aabb;
 use super::color::Color;
 use super::hittable::{HitRecord, Hittable};
+use super::hittable_list::HittableList;
 use super::interval::{self, Interval};
-use super::material::{Isotropic, Material};
+use super::material::{self, Anisotropic, Isotropic, Material, ScatterRecord};
+use super::phase::PhaseFunction;
 use super::ray::Ray;
 use super::rtweekend;
+use super::spectrum;
 use super::texture::{SolidColor, Texture};
 use super::vec3::Vec3;
 
 pub struct ConstantMedium<H: Hittable, M: Material> {
-    boundary: H,
-    neg_inv_density: f64,
+    boundary: Arc<H>,
+    // 各通道的消光系数。各通道不同时，每次求交随机选择一个通道采样自由程，
+    // 三个通道都保留，按谱 MIS 加权。
+    sigma_t: Color,
+    chromatic: bool,
+    // 所属嵌套物体的优先级。光线位于优先级更高的物体内时介质不起作用。
+    priority: Option<u32>,
     phase_function: M,
 }
 
 impl<H: Hittable + 'static, T: Texture + 'static> ConstantMedium<H, Isotropic<T>> {
     pub fn new(b: H, d: f64, a: T) -> Self {
         Self {
-            boundary: b,
-            neg_inv_density: -1.0 / d,
+            boundary: Arc::new(b),
+            sigma_t: Color::new(d, d, d),
+            chromatic: false,
+            priority: None,
             phase_function: Isotropic::new(a),
         }
     }
@@ -29,62 +39,126 @@ impl<H: Hittable + 'static, T: Texture + 'static> ConstantMedium<H, Isotropic<T>
 impl<H: Hittable + 'static> ConstantMedium<H, Isotropic<SolidColor>> {
     pub fn new_with_color(b: H, d: f64, c: Color) -> Self {
         Self {
-            boundary: b,
-            neg_inv_density: -1.0 / d,
+            boundary: Arc::new(b),
+            sigma_t: Color::new(d, d, d),
+            chromatic: false,
+            priority: None,
             phase_function: Isotropic::new_with_color(c),
         }
     }
+
+    // 由各通道的吸收系数 sigma_a 和散射系数 sigma_s 描述的介质，例如海水和皮肤。
+    pub fn new_with_coefficients(b: H, sigma_a: Color, sigma_s: Color) -> Self {
+        let sigma_t = sigma_a + sigma_s;
+        let albedo = |c: usize| {
+            if sigma_t[c] > 0.0 {
+                sigma_s[c] / sigma_t[c]
+            } else {
+                0.0
+            }
+        };
+        Self {
+            boundary: Arc::new(b),
+            sigma_t,
+            chromatic: sigma_t.x() != sigma_t.y() || sigma_t.y() != sigma_t.z(),
+            priority: None,
+            phase_function: Isotropic::new_with_color(Color::new(albedo(0), albedo(1), albedo(2))),
+        }
+    }
+}
+
+impl<H: Hittable + 'static, T: Texture + 'static, P: PhaseFunction + Clone + 'static>
+    ConstantMedium<H, Anisotropic<T, P>>
+{
+    // 按相函数 phase 散射的均匀介质。
+    pub fn new_with_phase(b: H, d: f64, a: T, phase: P) -> Self {
+        Self {
+            boundary: Arc::new(b),
+            sigma_t: Color::new(d, d, d),
+            chromatic: false,
+            priority: None,
+            phase_function: Anisotropic::new(a, phase),
+        }
+    }
+}
+
+impl<H: Hittable, M: Material + Clone> Clone for ConstantMedium<H, M> {
+    fn clone(&self) -> Self {
+        Self {
+            boundary: Arc::clone(&self.boundary),
+            sigma_t: self.sigma_t,
+            chromatic: self.chromatic,
+            priority: self.priority,
+            phase_function: self.phase_function.clone(),
+        }
+    }
+}
+
+impl<H: Hittable, M: Material> ConstantMedium<H, M> {
+    // 作为带优先级的电介质（例如杯中的液体）内部的介质，优先级与电介质相同。
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    // 光线各分量上的消光系数。带有波长的光线三个分量相同，谱渲染时上采样到光线的几个波长上。
+    fn extinction(&self, r: &Ray) -> Color {
+        if !self.chromatic {
+            return self.sigma_t;
+        }
+        match (r.hero_wavelength() > 0.0, r.wavelength() > 0.0) {
+            (true, true) => Color::one() * spectrum::from_rgb(self.sigma_t, r.wavelength()).x(),
+            (true, false) => spectrum::from_rgb(self.sigma_t, r.hero_wavelength()),
+            (false, _) => match material::rgb_channel(r.wavelength()) {
+                Some(channel) => Color::one() * self.sigma_t[channel],
+                None => self.sigma_t,
+            },
+        }
+    }
+
+    fn active(&self, r: &Ray) -> bool {
+        self.priority
+            .is_none_or(|priority| r.interior_priority().is_none_or(|top| top <= priority))
+    }
 }
 
-impl<H: Hittable + 'static, T: Texture + 'static> Hittable for ConstantMedium<H, Isotropic<T>> {
+impl<H: Hittable + 'static, M: Material + Clone + 'static> Hittable for ConstantMedium<H, M> {
     fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
         // Print occasional samples when debugging. To enable, set enableDebug true.
         const ENABLE_DEBUG: bool = false;
         let debugging = ENABLE_DEBUG && rtweekend::random_double() < 0.00001;
 
-        let mut rec1 = HitRecord::default();
-        let mut rec2 = HitRecord::default();
-
-        if !self.boundary.hit(r, &interval::UNIVERSE, &mut rec1) {
+        if !self.active(r) {
             return false;
         }
 
-        if !self.boundary.hit(
-            r,
-            &Interval::new(rec1.t + 0.0001, rtweekend::INFINITY),
-            &mut rec2,
-        ) {
+        let Some(inside) = boundary_interval(self.boundary.as_ref(), r, ray_t) else {
             return false;
-        }
+        };
 
         if debugging {
-            eprintln!("\nray_tmin={} ray_tmax={}", rec1.t, rec2.t);
-        }
-
-        if rec1.t < ray_t.min {
-            rec1.t = ray_t.min;
-        }
-        if rec2.t > ray_t.max {
-            rec2.t = ray_t.max;
-        }
-
-        if rec1.t >= rec2.t {
-            return false;
+            eprintln!("\nray_tmin={} ray_tmax={}", inside.min, inside.max);
         }
 
-        if rec1.t < 0.0 {
-            rec1.t = 0.0;
-        }
+        // 各通道的消光系数不同时随机选择一个通道采样自由程。
+        let sigma_t = self.extinction(r);
+        let gray = sigma_t.x() == sigma_t.y() && sigma_t.y() == sigma_t.z();
+        let channel = if gray {
+            0
+        } else {
+            rtweekend::random_int(0, 2) as usize
+        };
+        let neg_inv_density = -1.0 / sigma_t[channel];
 
         let ray_length = r.direction().length();
-        let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let hit_distance = self.neg_inv_density * rtweekend::random_double().ln();
+        let distance_inside_boundary = (inside.max - inside.min) * ray_length;
+        let hit_distance = neg_inv_density * rtweekend::random_double().ln();
 
         if hit_distance > distance_inside_boundary {
             return false;
         }
 
-        rec.t = rec1.t + hit_distance / ray_length;
+        rec.t = inside.min + hit_distance / ray_length;
         rec.p = r.at(rec.t);
 
         if debugging {
@@ -95,7 +169,27 @@ impl<H: Hittable + 'static, T: Texture + 'static> Hittable for ConstantMedium<H,
 
         rec.normal = Vec3::new(1.0, 0.0, 0.0); // arbitrary
         rec.front_face = true; // also arbitrary
-        rec.mat = Some(Arc::new(self.phase_function.clone()));
+        rec.mat = if gray {
+            Some(Arc::new(self.phase_function.clone()))
+        } else {
+            // 积分器已经为到这里的一段乘以 透射率 / 平均透射率，碰撞的概率密度是各通道
+            // sigma_t * 透射率 的平均值，这里补上剩下的部分。
+            let transmittance = Color::new(
+                (-sigma_t.x() * hit_distance).exp(),
+                (-sigma_t.y() * hit_distance).exp(),
+                (-sigma_t.z() * hit_distance).exp(),
+            );
+            let density = sigma_t * transmittance;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            if pdf <= 0.0 {
+                return false;
+            }
+            let average = (transmittance.x() + transmittance.y() + transmittance.z()) / 3.0;
+            Some(Arc::new(Collision {
+                material: self.phase_function.clone(),
+                weight: sigma_t * (average / pdf),
+            }))
+        };
 
         true
     }
@@ -103,4 +197,87 @@ impl<H: Hittable + 'static, T: Texture + 'static> Hittable for ConstantMedium<H,
     fn bounding_box(&self) -> &Aabb {
         self.boundary.bounding_box()
     }
+
+    fn shadow_hit(&self, _r: &Ray, _ray_t: &Interval, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    fn collect_media(&self, media: &mut HittableList) {
+        if self.chromatic {
+            media.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        if !self.active(r) {
+            return Color::one();
+        }
+        let Some(inside) = boundary_interval(self.boundary.as_ref(), r, ray_t) else {
+            return Color::one();
+        };
+        let distance_inside_boundary = (inside.max - inside.min) * r.direction().length();
+        let sigma_t = self.extinction(r);
+        Color::new(
+            (-sigma_t.x() * distance_inside_boundary).exp(),
+            (-sigma_t.y() * distance_inside_boundary).exp(),
+            (-sigma_t.z() * distance_inside_boundary).exp(),
+        )
+    }
+}
+
+// 有色介质中的一次碰撞，按 material 散射并乘以谱 MIS 的权重。material 的 bsdf_cos
+// 由 srec.attenuation 给出颜色，介质的相函数材质都是这样。
+#[derive(Clone)]
+struct Collision<M: Material> {
+    material: M,
+    weight: Color,
+}
+
+impl<M: Material> Material for Collision<M> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        if !self.material.scatter(r_in, rec, srec) {
+            return false;
+        }
+        srec.attenuation = srec.attenuation * self.weight;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.material.bsdf_cos(r_in, rec, srec, scattered)
+    }
+}
+
+// 光线在 ray_t 区间内位于封闭边界 boundary 之内的部分，假设光线最多穿过边界一次。
+pub fn boundary_interval<H: Hittable>(boundary: &H, r: &Ray, ray_t: &Interval) -> Option<Interval> {
+    let mut rec1 = HitRecord::default();
+    let mut rec2 = HitRecord::default();
+
+    if !boundary.hit(r, &interval::UNIVERSE, &mut rec1) {
+        return None;
+    }
+
+    if !boundary.hit(
+        r,
+        &Interval::new(rec1.t + 0.0001, rtweekend::INFINITY),
+        &mut rec2,
+    ) {
+        return None;
+    }
+
+    let t_min = rec1.t.max(ray_t.min).max(0.0);
+    let t_max = rec2.t.min(ray_t.max);
+    if t_min >= t_max {
+        return None;
+    }
+    Some(Interval::new(t_min, t_max))
 }
@@ -0,0 +1,500 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::color::{self, Color};
+use super::hittable::{HitRecord, Hittable};
+use super::interval::Interval;
+use super::onb::Onb;
+use super::ray::Ray;
+use super::rtw_stb_image::RtwImage;
+use super::rtweekend;
+use super::vec3::{self, Point3, Vec3};
+
+// 无穷远处的环境光。光线没有命中任何物体时看到它，也可以作为光源被采样。
+pub trait Environment: Send + Sync {
+    // 沿 direction 方向看到的辐亮度。
+    fn value(&self, direction: Vec3) -> Color;
+
+    // 所有方向上的平均辐亮度，用于估计环境光的功率。
+    fn average(&self) -> Color;
+
+    fn pdf_value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * rtweekend::PI)
+    }
+
+    fn random(&self) -> Vec3 {
+        vec3::random_unit_vector()
+    }
+}
+
+// 按行存储的分段常数二维分布：先按边缘分布选择一行，再按条件分布选择这一行中的一列。
+struct Distribution2D {
+    func: Vec<f64>,
+    conditional_cdf: Vec<Vec<f64>>,
+    marginal_cdf: Vec<f64>,
+    width: usize,
+    height: usize,
+    total: f64,
+}
+
+impl Distribution2D {
+    fn new(mut func: Vec<f64>, width: usize, height: usize) -> Self {
+        let mut total: f64 = func.iter().sum();
+        if total <= 0.0 {
+            func.iter_mut().for_each(|f| *f = 1.0);
+            total = (width * height) as f64;
+        }
+
+        let cdf = |values: &[f64]| {
+            let sum: f64 = values.iter().sum();
+            let mut cdf = Vec::with_capacity(values.len() + 1);
+            let mut acc = 0.0;
+            cdf.push(0.0);
+            for (i, v) in values.iter().enumerate() {
+                acc += v;
+                cdf.push(if sum > 0.0 {
+                    acc / sum
+                } else {
+                    (i + 1) as f64 / values.len() as f64
+                });
+            }
+            cdf
+        };
+
+        let conditional_cdf: Vec<Vec<f64>> = func.chunks_exact(width).map(cdf).collect();
+        let rows: Vec<f64> = func
+            .chunks_exact(width)
+            .map(|row| row.iter().sum())
+            .collect();
+        let marginal_cdf = cdf(&rows);
+
+        Self {
+            func,
+            conditional_cdf,
+            marginal_cdf,
+            width,
+            height,
+            total,
+        }
+    }
+
+    fn sample_cdf(cdf: &[f64], xi: f64) -> usize {
+        // cdf[i + 1] > xi 的第一个 i，跳过概率为零的区间。
+        (cdf.partition_point(|&c| c <= xi) - 1).min(cdf.len() - 2)
+    }
+
+    // 返回选中像素中均匀分布的 (u, v) ∈ [0, 1)²，u 沿着行，v 从上往下。
+    fn sample(&self) -> (f64, f64) {
+        let row = Self::sample_cdf(&self.marginal_cdf, rtweekend::random_double());
+        let col = Self::sample_cdf(&self.conditional_cdf[row], rtweekend::random_double());
+        (
+            (col as f64 + rtweekend::random_double()) / self.width as f64,
+            (row as f64 + rtweekend::random_double()) / self.height as f64,
+        )
+    }
+
+    // (u, v) 处相对于 [0, 1]² 面积的概率密度。
+    fn pdf(&self, u: f64, v: f64) -> f64 {
+        let col = ((u * self.width as f64) as usize).min(self.width - 1);
+        let row = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.func[row * self.width + col] * (self.width * self.height) as f64 / self.total
+    }
+}
+
+// 等距柱状投影（经纬度）的 HDR 环境贴图，按亮度重要性采样。
+pub struct EnvironmentMap {
+    image: RtwImage,
+    intensity: f64,
+    sin_rotation: f64,
+    cos_rotation: f64,
+    distribution: Distribution2D,
+    average: Color,
+}
+
+impl EnvironmentMap {
+    // 方向与纹理坐标的对应关系和 Sphere 的纹理坐标相同。
+    pub fn new(filename: &str) -> Self {
+        let image = RtwImage::new(filename);
+        let (width, height) = (image.width().max(1), image.height().max(1));
+
+        // 每个像素对应的立体角与 sin(theta) 成正比，从上往下 theta 从 π 变到 0。
+        let mut func = Vec::with_capacity(width * height);
+        let mut sum = Color::default();
+        let mut weight = 0.0;
+        for j in 0..height {
+            let sin_theta = (rtweekend::PI * (j as f64 + 0.5) / height as f64).sin();
+            for i in 0..width {
+                let pixel = image.pixel_data(i, j);
+                let c = Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+                func.push(color::luminance(c).max(0.0) * sin_theta);
+                sum += sin_theta * c;
+                weight += sin_theta;
+            }
+        }
+
+        Self {
+            image,
+            intensity: 1.0,
+            sin_rotation: 0.0,
+            cos_rotation: 1.0,
+            distribution: Distribution2D::new(func, width, height),
+            average: sum / weight,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    // 绕 y 轴旋转环境贴图，单位为度。
+    pub fn with_rotation(mut self, angle: f64) -> Self {
+        let radians = angle.to_radians();
+        self.sin_rotation = radians.sin();
+        self.cos_rotation = radians.cos();
+        self
+    }
+
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        // 先把世界方向转回贴图的局部方向。
+        let d = vec3::unit_vector(direction);
+        let x = self.cos_rotation * d.x() - self.sin_rotation * d.z();
+        let z = self.sin_rotation * d.x() + self.cos_rotation * d.z();
+        let theta = (-d.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-z).atan2(x) + rtweekend::PI;
+        (phi / (2.0 * rtweekend::PI), 1.0 - theta / rtweekend::PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = (1.0 - v) * rtweekend::PI;
+        let phi = u * 2.0 * rtweekend::PI - rtweekend::PI;
+        let x = theta.sin() * phi.cos();
+        let z = -theta.sin() * phi.sin();
+        Vec3::new(
+            self.cos_rotation * x + self.sin_rotation * z,
+            -theta.cos(),
+            -self.sin_rotation * x + self.cos_rotation * z,
+        )
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn value(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let i = (u * self.image.width() as f64) as usize;
+        let j = (v * self.image.height() as f64) as usize;
+        let pixel = self.image.pixel_data(i, j);
+        self.intensity * Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64)
+    }
+
+    fn average(&self) -> Color {
+        self.intensity * self.average
+    }
+
+    fn pdf_value(&self, direction: Vec3) -> f64 {
+        // 从 (u, v) 到立体角的雅可比为 2π² sin(theta)。
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (rtweekend::PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * rtweekend::PI * rtweekend::PI * sin_theta)
+    }
+
+    fn random(&self) -> Vec3 {
+        let (u, v) = self.distribution.sample();
+        self.uv_to_direction(u, v)
+    }
+}
+
+// 把环境光放进光源列表。它不能被光线命中（由相机在光线没有命中任何物体时处理），
+// 只提供光源采样需要的 pdf_value、random 和功率。
+pub struct EnvironmentLight {
+    environment: Arc<dyn Environment>,
+    bbox: Aabb,
+    radius: f64,
+}
+
+impl EnvironmentLight {
+    // scene_bbox 为场景的包围盒，功率按照照亮整个场景所需的功率估计。
+    pub fn new(environment: Arc<dyn Environment>, scene_bbox: &Aabb) -> Self {
+        let radius = 0.5
+            * Vec3::new(
+                scene_bbox.x.size(),
+                scene_bbox.y.size(),
+                scene_bbox.z.size(),
+            )
+            .length();
+        Self {
+            environment,
+            bbox: scene_bbox.clone(),
+            radius,
+        }
+    }
+}
+
+impl Hittable for EnvironmentLight {
+    fn hit(&self, _r: &Ray, _ray_t: &Interval, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
+
+    fn pdf_value(&self, _origin: Point3, direction: Vec3) -> f64 {
+        self.environment.pdf_value(direction)
+    }
+
+    fn random(&self, _origin: Point3) -> Vec3 {
+        self.environment.random()
+    }
+
+    fn power(&self) -> f64 {
+        rtweekend::PI * self.radius * self.radius * color::luminance(self.environment.average())
+    }
+
+    fn emission_power(&self) -> f64 {
+        0.0
+    }
+}
+
+// Preetham 天空模型给出的亮度单位为 kcd/m²，乘以这个系数换算到渲染器常用的范围。
+const SKY_SCALE: f64 = 0.05;
+// 大气层外太阳圆盘的亮度（kcd/m²）和视半径（度）。
+const SUN_LUMINANCE: f64 = 1.6e6;
+const SUN_ANGULAR_RADIUS: f64 = 0.2667;
+
+// Preetham 等人的解析天空模型，加上一个可以被采样的太阳圆盘。y 轴朝上，地平线以下为黑色，
+// 地面应当由场景中的几何体提供。
+pub struct Sky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    intensity: f64,
+    // 五个分布系数 A..E，分别对应亮度 Y 和色度 x、y。
+    coefficients: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+    sun_probability: f64,
+    average: Color,
+}
+
+impl Sky {
+    // elevation 为太阳高出地平线的角度，azimuth 为从 -z 方向绕 y 轴转向 +x 方向的角度，单位都是度。
+    // turbidity 为大气浑浊度，晴朗的天空约为 2，有雾霾时可以到 10。
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let mut sky = Self {
+            sun_direction,
+            turbidity,
+            intensity: 1.0,
+            coefficients: [[0.0; 5]; 3],
+            zenith: [0.0; 3],
+            sun_radiance: Color::default(),
+            cos_sun_radius: SUN_ANGULAR_RADIUS.to_radians().cos(),
+            sun_probability: 0.0,
+            average: Color::default(),
+        };
+        sky.update();
+        sky
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self.update();
+        self
+    }
+
+    // 改变太阳的视半径（度）。圆盘越大阴影越软，太阳的总功率保持不变。
+    pub fn with_sun_radius(mut self, degrees: f64) -> Self {
+        self.cos_sun_radius = degrees.max(1e-3).to_radians().cos();
+        self.update();
+        self
+    }
+
+    fn update(&mut self) {
+        let t = self.turbidity;
+        let theta_s = self.sun_direction.y().clamp(0.0, 1.0).acos();
+
+        self.coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // 天顶的亮度和色度。
+        let chi = (4.0 / 9.0 - t / 120.0) * (rtweekend::PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let row = |r: [f64; 4]| (0..4).map(|i| r[i] * theta[i]).sum::<f64>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y_chroma = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        self.zenith = [zenith_y.max(0.0), zenith_x, zenith_y_chroma];
+
+        // 太阳光穿过大气层时的瑞利散射和气溶胶衰减，光学质量按 Kasten 的公式计算。
+        let elevation_deg = 90.0 - theta_s.to_degrees();
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (elevation_deg + 3.885).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = |lambda_um: f64| {
+            let rayleigh = (-0.008735 * lambda_um.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda_um.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        let sun_color = color::blackbody(5778.0);
+        let real_solid_angle = 2.0 * rtweekend::PI * (1.0 - SUN_ANGULAR_RADIUS.to_radians().cos());
+        self.sun_radiance = if self.sun_direction.y() > 0.0 {
+            SUN_LUMINANCE * real_solid_angle / self.sun_solid_angle()
+                * SKY_SCALE
+                * self.intensity
+                * Color::new(
+                    sun_color.x() * transmittance(0.65),
+                    sun_color.y() * transmittance(0.55),
+                    sun_color.z() * transmittance(0.45),
+                )
+        } else {
+            Color::default()
+        };
+
+        // 天空在整个球面上的平均辐亮度（地平线以下为零）。
+        let (n_theta, n_phi) = (32, 64);
+        let mut sum = Color::default();
+        for i in 0..n_theta {
+            let theta = 0.5 * rtweekend::PI * (i as f64 + 0.5) / n_theta as f64;
+            for j in 0..n_phi {
+                let phi = 2.0 * rtweekend::PI * (j as f64 + 0.5) / n_phi as f64;
+                let d = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                sum += theta.sin() * self.sky_radiance(d);
+            }
+        }
+        let d_omega = (0.5 * rtweekend::PI / n_theta as f64) * (2.0 * rtweekend::PI / n_phi as f64);
+        self.average = sum * d_omega / (4.0 * rtweekend::PI);
+
+        // 按太阳和天空各自贡献的功率决定采样太阳圆盘的概率。
+        let sun_power = color::luminance(self.sun_radiance) * self.sun_solid_angle();
+        let sky_power = color::luminance(self.average) * 4.0 * rtweekend::PI;
+        self.sun_probability = if sun_power + sky_power > 0.0 {
+            sun_power / (sun_power + sky_power)
+        } else {
+            0.0
+        };
+    }
+
+    fn sun_solid_angle(&self) -> f64 {
+        2.0 * rtweekend::PI * (1.0 - self.cos_sun_radius)
+    }
+
+    fn perez(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = *coefficients;
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        let d = vec3::unit_vector(direction);
+        if d.y() <= 0.0 {
+            return Color::default();
+        }
+        let cos_theta = d.y().max(0.01);
+        let gamma = vec3::dot(d, self.sun_direction).clamp(-1.0, 1.0).acos();
+        let theta_s = self.sun_direction.y().clamp(0.0, 1.0).acos();
+
+        let [y, x, y_chroma] = [0, 1, 2].map(|i| {
+            self.zenith[i] * Self::perez(&self.coefficients[i], cos_theta, gamma)
+                / Self::perez(&self.coefficients[i], 1.0, theta_s)
+        });
+        if y_chroma <= 0.0 {
+            return Color::default();
+        }
+        let xyz = Vec3::new(x / y_chroma * y, y, (1.0 - x - y_chroma) / y_chroma * y);
+        SKY_SCALE * self.intensity * color::xyz_to_linear_srgb(xyz)
+    }
+
+    fn in_sun(&self, direction: Vec3) -> bool {
+        vec3::dot(vec3::unit_vector(direction), self.sun_direction) >= self.cos_sun_radius
+    }
+}
+
+impl Environment for Sky {
+    fn value(&self, direction: Vec3) -> Color {
+        let sky = self.sky_radiance(direction);
+        if self.in_sun(direction) && direction.y() > 0.0 {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    fn average(&self) -> Color {
+        self.average + self.sun_radiance * self.sun_solid_angle() / (4.0 * rtweekend::PI)
+    }
+
+    fn pdf_value(&self, direction: Vec3) -> f64 {
+        // 天空部分在上半球均匀采样，太阳部分在圆盘对应的圆锥内均匀采样。
+        let sky_pdf = if direction.y() > 0.0 {
+            1.0 / (2.0 * rtweekend::PI)
+        } else {
+            0.0
+        };
+        let sun_pdf = if self.in_sun(direction) {
+            1.0 / self.sun_solid_angle()
+        } else {
+            0.0
+        };
+        self.sun_probability * sun_pdf + (1.0 - self.sun_probability) * sky_pdf
+    }
+
+    fn random(&self) -> Vec3 {
+        if rtweekend::random_double() < self.sun_probability {
+            let cos_theta = 1.0 - rtweekend::random_double() * (1.0 - self.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * rtweekend::PI * rtweekend::random_double();
+            let uvw = Onb::new_from_w(self.sun_direction);
+            uvw.local_v(Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+        } else {
+            let d = vec3::random_unit_vector();
+            Vec3::new(d.x(), d.y().abs(), d.z())
+        }
+    }
+}
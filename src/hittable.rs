@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
 use super::aabb::Aabb;
+use super::color::Color;
+use super::hittable_list::HittableList;
 use super::interval::Interval;
 use super::material::Material;
 use super::ray::Ray;
@@ -10,12 +12,20 @@ use super::vec3::{self, Point3, Vec3};
 #[derive(Clone, Default)]
 pub struct HitRecord {
     pub p: Point3,
+    // 着色法线，材质计算使用它；可能被插值或法线贴图扰动。
     pub normal: Vec3,
+    // 几何法线，总是与 normal 位于表面的同一侧。
+    pub geom_normal: Vec3,
+    // 位置对纹理坐标的偏导数，构成切线空间。
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub mat: Option<Arc<dyn Material>>,
     pub t: f64,
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // 从带优先级的电介质表面穿出后一侧的折射率，由积分器按内部栈填入。
+    pub outside_ior: Option<f64>,
 }
 
 pub trait Hittable: Send + Sync {
@@ -27,6 +37,93 @@ pub trait Hittable: Send + Sync {
     fn random(&self, _origin: Point3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+    // 把其中材质发光的图元加入 lights，供光源采样使用。
+    fn collect_lights(&self, _lights: &mut HittableList) {}
+    // 把其中各通道消光系数不同的介质加入 media，积分器为光线在它们中没有碰撞的每一段计算权重。
+    fn collect_media(&self, _media: &mut HittableList) {}
+    // 发光图元向外发射的总功率（按亮度计），光源采样按它的比例选择光源。
+    fn power(&self) -> f64 {
+        0.0
+    }
+    // 阴影光线的求交。参与介质在这里不产生命中，它们的衰减由 transmittance 给出。
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, hit_record: &mut HitRecord) -> bool {
+        self.hit(r, ray_t, hit_record)
+    }
+    // 光线在 ray_t 区间内穿过参与介质的透射率。表面的遮挡已由 shadow_hit 处理，所以默认为 1。
+    fn transmittance(&self, _r: &Ray, _ray_t: &Interval) -> Color {
+        Color::one()
+    }
+    // 在发光图元的表面上随机取一点作为光源子路径的起点，填入 rec 的位置、朝外的法线
+    // （front_face 为 true）、纹理坐标和材质，返回按面积的概率密度（包括选中这个图元的概率）。
+    // 不能这样采样的光源（例如环境光）返回 0。
+    fn sample_emission(&self, _rec: &mut HitRecord) -> f64 {
+        0.0
+    }
+    // sample_emission 取到光线 r 在 rec.t 处命中的那一点的概率密度（按面积）。
+    fn emission_pdf(&self, _r: &Ray, _rec: &HitRecord) -> f64 {
+        0.0
+    }
+    // 选择发射光源子路径的光源时使用的功率。不能用 sample_emission 采样的光源为 0，
+    // 这样不会选中它们。
+    fn emission_power(&self) -> f64 {
+        self.power()
+    }
+}
+
+// emission_pdf 用这个区间确认光线在 t 处命中的就是这个图元。
+pub fn emission_interval(t: f64) -> Interval {
+    Interval::new(t * (1.0 - 1e-6), t * (1.0 + 1e-6))
+}
+
+// 共享的物体，光源列表中的图元和场景中的图元是同一个对象。
+impl Hittable for Arc<dyn Hittable> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, hit_record: &mut HitRecord) -> bool {
+        self.as_ref().hit(r, ray_t, hit_record)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.as_ref().bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.as_ref().random(origin)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        self.as_ref().collect_lights(lights)
+    }
+
+    fn collect_media(&self, media: &mut HittableList) {
+        self.as_ref().collect_media(media)
+    }
+
+    fn power(&self) -> f64 {
+        self.as_ref().power()
+    }
+
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, hit_record: &mut HitRecord) -> bool {
+        self.as_ref().shadow_hit(r, ray_t, hit_record)
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        self.as_ref().transmittance(r, ray_t)
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        self.as_ref().sample_emission(rec)
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        self.as_ref().emission_pdf(r, rec)
+    }
+
+    fn emission_power(&self) -> f64 {
+        self.as_ref().emission_power()
+    }
 }
 
 impl HitRecord {
@@ -37,6 +134,21 @@ impl HitRecord {
         } else {
             -outward_normal
         };
+        self.geom_normal = self.normal;
+    }
+
+    pub fn set_shading_normal(&mut self, outward_shading_normal: Vec3) {
+        // 在 set_face_normal 之后调用，把着色法线翻转到与几何法线相同的一侧。
+        self.normal = if self.front_face {
+            outward_shading_normal
+        } else {
+            -outward_shading_normal
+        };
+    }
+
+    pub fn set_tangents(&mut self, dpdu: Vec3, dpdv: Vec3) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
     }
 }
 
@@ -55,11 +167,18 @@ impl<T: Hittable> Translate<T> {
             bbox,
         }
     }
+
+    fn object_ray(&self, r: &Ray) -> Ray {
+        Ray::new_with_time(r.origin() - self.offset, r.direction(), r.time())
+            .with_wavelength(r.wavelength())
+            .with_hero_wavelength(r.hero_wavelength())
+            .with_interior_priority(r.interior_priority())
+    }
 }
 
 impl<T: Hittable> Hittable for Translate<T> {
     fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
-        let offset_r = Ray::new_with_time(r.origin() - self.offset, r.direction(), r.time());
+        let offset_r = self.object_ray(r);
 
         if !self.object.hit(&offset_r, ray_t, rec) {
             return false;
@@ -70,9 +189,60 @@ impl<T: Hittable> Hittable for Translate<T> {
         true
     }
 
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.object.shadow_hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        rec.p += self.offset;
+        true
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        self.object.transmittance(&self.object_ray(r), ray_t)
+    }
+
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin - self.offset, direction)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.object.random(origin - self.offset)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        // 每个光源单独包装，光源采样才能区分它们的功率和位置。
+        let mut object = HittableList::default();
+        self.object.collect_lights(&mut object);
+        for light in object.objects {
+            lights.add(Arc::new(Translate::new(light, self.offset)));
+        }
+    }
+
+    fn collect_media(&self, media: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_media(&mut object);
+        for medium in object.objects {
+            media.add(Arc::new(Translate::new(medium, self.offset)));
+        }
+    }
+
+    fn power(&self) -> f64 {
+        self.object.power()
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let pdf = self.object.sample_emission(rec);
+        rec.p += self.offset;
+        pdf
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        self.object.emission_pdf(&self.object_ray(r), rec)
+    }
 }
 
 pub struct RotateY<T: Hittable> {
@@ -85,8 +255,10 @@ pub struct RotateY<T: Hittable> {
 impl<T: Hittable> RotateY<T> {
     pub fn new(p: T, angle: f64) -> Self {
         let radians = angle.to_radians();
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+        Self::new_with_sin_cos(p, radians.sin(), radians.cos())
+    }
+
+    fn new_with_sin_cos(p: T, sin_theta: f64, cos_theta: f64) -> Self {
         let bbox = p.bounding_box();
         let mut min = Point3::new(
             rtweekend::INFINITY,
@@ -122,41 +294,112 @@ impl<T: Hittable> RotateY<T> {
             bbox,
         }
     }
+
+    fn rotate(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    fn rotate_inverse(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    fn object_ray(&self, r: &Ray) -> Ray {
+        let origin = self.rotate_inverse(r.origin());
+        let direction = self.rotate_inverse(r.direction());
+        Ray::new_with_time(origin, direction, r.time())
+            .with_wavelength(r.wavelength())
+            .with_hero_wavelength(r.hero_wavelength())
+            .with_interior_priority(r.interior_priority())
+    }
+
+    fn record_to_world(&self, rec: &mut HitRecord) {
+        rec.p = self.rotate(rec.p);
+        rec.normal = self.rotate(rec.normal);
+        rec.geom_normal = self.rotate(rec.geom_normal);
+        rec.dpdu = self.rotate(rec.dpdu);
+        rec.dpdv = self.rotate(rec.dpdv);
+    }
 }
 
 impl<T: Hittable> Hittable for RotateY<T> {
     fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
-        let mut origin = r.origin();
-        let mut direction = r.direction();
+        if !self.object.hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        self.record_to_world(rec);
+        true
+    }
+
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.object.shadow_hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        self.record_to_world(rec);
+        true
+    }
 
-        origin[0] = self.cos_theta * r.origin()[0] - self.sin_theta * r.origin()[2];
-        origin[2] = self.sin_theta * r.origin()[0] + self.cos_theta * r.origin()[2];
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        self.object.transmittance(&self.object_ray(r), ray_t)
+    }
 
-        direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
-        direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
 
-        let rotated_r = Ray::new_with_time(origin, direction, r.time());
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object
+            .pdf_value(self.rotate_inverse(origin), self.rotate_inverse(direction))
+    }
 
-        if !self.object.hit(&rotated_r, ray_t, rec) {
-            return false;
-        }
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.rotate(self.object.random(self.rotate_inverse(origin)))
+    }
 
-        let mut p = rec.p;
-        p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-        p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_lights(&mut object);
+        for light in object.objects {
+            lights.add(Arc::new(RotateY::new_with_sin_cos(
+                light,
+                self.sin_theta,
+                self.cos_theta,
+            )));
+        }
+    }
 
-        let mut normal = rec.normal;
-        normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
+    fn collect_media(&self, media: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_media(&mut object);
+        for medium in object.objects {
+            media.add(Arc::new(RotateY::new_with_sin_cos(
+                medium,
+                self.sin_theta,
+                self.cos_theta,
+            )));
+        }
+    }
 
-        rec.p = p;
-        rec.normal = normal;
+    fn power(&self) -> f64 {
+        self.object.power()
+    }
 
-        true
+    // 旋转不改变面积，概率密度保持不变。
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let pdf = self.object.sample_emission(rec);
+        self.record_to_world(rec);
+        pdf
     }
 
-    fn bounding_box(&self) -> &Aabb {
-        &self.bbox
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        self.object.emission_pdf(&self.object_ray(r), rec)
     }
 }
 
@@ -170,8 +413,10 @@ pub struct RotateX<T: Hittable> {
 impl<T: Hittable> RotateX<T> {
     pub fn new(p: T, angle: f64) -> Self {
         let radians = angle.to_radians();
-        let sin_theta = radians.sin();
-        let cos_theta = radians.cos();
+        Self::new_with_sin_cos(p, radians.sin(), radians.cos())
+    }
+
+    fn new_with_sin_cos(p: T, sin_theta: f64, cos_theta: f64) -> Self {
         let bbox = p.bounding_box();
         let mut min = Point3::new(
             rtweekend::INFINITY,
@@ -207,41 +452,111 @@ impl<T: Hittable> RotateX<T> {
             bbox,
         }
     }
+
+    fn rotate(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            v[0],
+            self.cos_theta * v[1] + self.sin_theta * v[2],
+            -self.sin_theta * v[1] + self.cos_theta * v[2],
+        )
+    }
+
+    fn rotate_inverse(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            v[0],
+            self.cos_theta * v[1] - self.sin_theta * v[2],
+            self.sin_theta * v[1] + self.cos_theta * v[2],
+        )
+    }
+
+    fn object_ray(&self, r: &Ray) -> Ray {
+        let origin = self.rotate_inverse(r.origin());
+        let direction = self.rotate_inverse(r.direction());
+        Ray::new_with_time(origin, direction, r.time())
+            .with_wavelength(r.wavelength())
+            .with_hero_wavelength(r.hero_wavelength())
+            .with_interior_priority(r.interior_priority())
+    }
+
+    fn record_to_world(&self, rec: &mut HitRecord) {
+        rec.p = self.rotate(rec.p);
+        rec.normal = self.rotate(rec.normal);
+        rec.geom_normal = self.rotate(rec.geom_normal);
+        rec.dpdu = self.rotate(rec.dpdu);
+        rec.dpdv = self.rotate(rec.dpdv);
+    }
 }
 
 impl<T: Hittable> Hittable for RotateX<T> {
     fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
-        let mut origin = r.origin();
-        let mut direction = r.direction();
+        if !self.object.hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        self.record_to_world(rec);
+        true
+    }
+
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.object.shadow_hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        self.record_to_world(rec);
+        true
+    }
 
-        origin[1] = self.cos_theta * r.origin()[1] - self.sin_theta * r.origin()[2];
-        origin[2] = self.sin_theta * r.origin()[1] + self.cos_theta * r.origin()[2];
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        self.object.transmittance(&self.object_ray(r), ray_t)
+    }
 
-        direction[1] = self.cos_theta * r.direction()[1] - self.sin_theta * r.direction()[2];
-        direction[2] = self.sin_theta * r.direction()[1] + self.cos_theta * r.direction()[2];
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
 
-        let rotated_r = Ray::new_with_time(origin, direction, r.time());
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object
+            .pdf_value(self.rotate_inverse(origin), self.rotate_inverse(direction))
+    }
 
-        if !self.object.hit(&rotated_r, ray_t, rec) {
-            return false;
-        }
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.rotate(self.object.random(self.rotate_inverse(origin)))
+    }
 
-        let mut p = rec.p;
-        p[1] = self.cos_theta * rec.p[1] + self.sin_theta * rec.p[2];
-        p[2] = -self.sin_theta * rec.p[1] + self.cos_theta * rec.p[2];
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_lights(&mut object);
+        for light in object.objects {
+            lights.add(Arc::new(RotateX::new_with_sin_cos(
+                light,
+                self.sin_theta,
+                self.cos_theta,
+            )));
+        }
+    }
 
-        let mut normal = rec.normal;
-        normal[1] = self.cos_theta * rec.normal[1] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[1] + self.cos_theta * rec.normal[2];
+    fn collect_media(&self, media: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_media(&mut object);
+        for medium in object.objects {
+            media.add(Arc::new(RotateX::new_with_sin_cos(
+                medium,
+                self.sin_theta,
+                self.cos_theta,
+            )));
+        }
+    }
 
-        rec.p = p;
-        rec.normal = normal;
+    fn power(&self) -> f64 {
+        self.object.power()
+    }
 
-        true
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let pdf = self.object.sample_emission(rec);
+        self.record_to_world(rec);
+        pdf
     }
 
-    fn bounding_box(&self) -> &Aabb {
-        &self.bbox
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        self.object.emission_pdf(&self.object_ray(r), rec)
     }
 }
 
@@ -262,8 +577,10 @@ impl<H: Hittable> Scale<H> {
         );
 
         let object_bbox = object.bounding_box();
-        let min_p = object_bbox.x.min * scale_vec;
-        let max_p = object_bbox.x.max * scale_vec;
+        let min_p =
+            Point3::new(object_bbox.x.min, object_bbox.y.min, object_bbox.z.min) * scale_vec;
+        let max_p =
+            Point3::new(object_bbox.x.max, object_bbox.y.max, object_bbox.z.max) * scale_vec;
 
         let bbox = Aabb::new_with_point(&min_p, &max_p);
 
@@ -276,25 +593,105 @@ impl<H: Hittable> Scale<H> {
     }
 }
 
-impl<H: Hittable> Hittable for Scale<H> {
-    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+impl<H: Hittable> Scale<H> {
+    fn object_ray(&self, r: &Ray) -> Ray {
         let origin = r.origin() * self.inv_scale;
         let direction = r.direction() * self.inv_scale;
-        let scaled_r = Ray::new_with_time(origin, direction, r.time());
+        Ray::new_with_time(origin, direction, r.time())
+            .with_wavelength(r.wavelength())
+            .with_hero_wavelength(r.hero_wavelength())
+            .with_interior_priority(r.interior_priority())
+    }
 
-        if !self.object.hit(&scaled_r, ray_t, rec) {
-            return false;
-        }
+    // 物体空间中法线为 normal 的面积元缩放后的面积之比（Nanson 公式）。
+    fn area_factor(&self, normal: Vec3) -> f64 {
+        let det = (self.scale.x() * self.scale.y() * self.scale.z()).abs();
+        det * (normal * self.inv_scale).length()
+    }
 
+    fn record_to_world(&self, rec: &mut HitRecord) {
+        // 法线按逆转置变换，切线按缩放变换。正缩放不会改变法线相对光线的朝向，
+        // 所以 front_face 保持不变。
         rec.p = rec.p * self.scale;
+        rec.normal = vec3::unit_vector(rec.normal * self.inv_scale);
+        rec.geom_normal = vec3::unit_vector(rec.geom_normal * self.inv_scale);
+        rec.dpdu = rec.dpdu * self.scale;
+        rec.dpdv = rec.dpdv * self.scale;
+    }
+}
 
-        let final_normal = vec3::unit_vector(rec.normal * self.inv_scale);
-        rec.set_face_normal(r, final_normal);
+impl<H: Hittable> Hittable for Scale<H> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.object.hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        self.record_to_world(rec);
+        true
+    }
 
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.object.shadow_hit(&self.object_ray(r), ray_t, rec) {
+            return false;
+        }
+        self.record_to_world(rec);
         true
     }
 
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        self.object.transmittance(&self.object_ray(r), ray_t)
+    }
+
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        // 缩放改变了立体角的度量：单位方向 w 映射到 A w / |A w|，雅可比为 |det A| / |A w|^3。
+        let direction = vec3::unit_vector(direction);
+        let scaled_direction = direction * self.inv_scale;
+        let det = (self.inv_scale.x() * self.inv_scale.y() * self.inv_scale.z()).abs();
+        self.object
+            .pdf_value(origin * self.inv_scale, scaled_direction)
+            * det
+            / scaled_direction.length().powi(3)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        self.object.random(origin * self.inv_scale) * self.scale
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_lights(&mut object);
+        for light in object.objects {
+            lights.add(Arc::new(Scale::new(light, self.scale)));
+        }
+    }
+
+    fn collect_media(&self, media: &mut HittableList) {
+        let mut object = HittableList::default();
+        self.object.collect_media(&mut object);
+        for medium in object.objects {
+            media.add(Arc::new(Scale::new(medium, self.scale)));
+        }
+    }
+
+    fn power(&self) -> f64 {
+        // 非均匀缩放时面积的变化与表面朝向有关，这里用体积缩放的 2/3 次方近似。
+        let det = (self.scale.x() * self.scale.y() * self.scale.z()).abs();
+        self.object.power() * det.powf(2.0 / 3.0)
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let pdf = self.object.sample_emission(rec);
+        let factor = self.area_factor(rec.geom_normal);
+        self.record_to_world(rec);
+        pdf / factor
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        // 世界空间的法线 n 对应物体空间中与 n * scale 平行的法线。
+        let object_normal = vec3::unit_vector(rec.geom_normal * self.scale);
+        self.object.emission_pdf(&self.object_ray(r), rec) / self.area_factor(object_normal)
+    }
 }
@@ -1,3 +1,4 @@
+use super::color::Color;
 use super::hittable::{HitRecord, Hittable};
 use super::ray::Ray;
 use super::rtweekend;
@@ -71,4 +72,66 @@ impl Hittable for HittableList {
         let int_size = self.objects.len() as i32;
         self.objects[rtweekend::random_int(0, int_size - 1) as usize].random(origin)
     }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in self.objects.iter() {
+            object.collect_lights(lights);
+        }
+    }
+
+    fn collect_media(&self, media: &mut HittableList) {
+        for object in self.objects.iter() {
+            object.collect_media(media);
+        }
+    }
+
+    fn power(&self) -> f64 {
+        self.objects.iter().map(|object| object.power()).sum()
+    }
+
+    fn emission_power(&self) -> f64 {
+        self.objects
+            .iter()
+            .map(|object| object.emission_power())
+            .sum()
+    }
+
+    fn shadow_hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        let mut temp_rec = HitRecord::default();
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        for object in self.objects.iter() {
+            if object.shadow_hit(r, &Interval::new(ray_t.min, closest_so_far), &mut temp_rec) {
+                hit_anything = true;
+                closest_so_far = temp_rec.t;
+                *rec = temp_rec.clone();
+            }
+        }
+
+        hit_anything
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        self.objects
+            .iter()
+            .fold(Color::one(), |t, object| t * object.transmittance(r, ray_t))
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let int_size = self.objects.len() as i32;
+        let object = &self.objects[rtweekend::random_int(0, int_size - 1) as usize];
+        object.sample_emission(rec) / self.objects.len() as f64
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.emission_pdf(r, rec))
+            .sum()
+    }
 }
@@ -0,0 +1,410 @@
+use std::sync::Arc;
+
+use super::camera::{Camera, Film};
+use super::color::Color;
+use super::hittable::{HitRecord, Hittable};
+use super::interior::InteriorStack;
+use super::interval::Interval;
+use super::light::AnalyticLight;
+use super::material;
+use super::onb::Onb;
+use super::pdf;
+use super::pdf::{HittablePdf, Pdf};
+use super::ray::Ray;
+use super::rtweekend;
+use super::spectrum;
+use super::vec3::{self, Vec3};
+
+// 由相机光线估计到达相机的辐亮度，结果为线性 sRGB。光源子路径直接连接到相机时
+// 贡献可能落在其他像素上，这部分累加到 film 中。
+pub trait Integrator: Send + Sync {
+    fn ray_color(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> Color;
+
+    // 需要在像素之间共享状态的积分器（例如逐遍细化的光子映射）自己渲染整幅图像，
+    // 按行返回每个像素 samples_per_pixel 个样本的辐亮度之和。默认返回 None，由相机逐个像素调用 ray_color。
+    fn render(
+        &self,
+        _camera: &Camera,
+        _world: &Arc<dyn Hittable>,
+        _lights: &Option<Arc<dyn Hittable>>,
+    ) -> Option<Vec<Color>> {
+        None
+    }
+}
+
+// 反弹的类型。漫反射包括按 PDF 采样的有粗糙度的反射，透射包括穿过表面的所有反弹。
+#[derive(Clone, Copy)]
+pub enum Bounce {
+    Diffuse,
+    Specular,
+    Transmission,
+    Volume,
+}
+
+// 单向路径追踪：每次非镜面反弹向光源采样一次，与按材质采样的方向用多重重要性采样结合。
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn ray_color(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        _film: &Film,
+    ) -> Color {
+        let mut ray = *r;
+        let mut radiance = Color::default();
+        let mut throughput = Color::one();
+        // 生成当前光线时按材质采样的概率密度；相机光线和镜面反射/折射的光线为 None，
+        // 它们命中光源时的自发光不参与多重重要性采样。
+        let mut bsdf_pdf: Option<f64> = None;
+        let mut bounces = [0; 4];
+        // 光线上已经处理过的部分。穿过虚假的交点时光线保持不变，只把求交的起点向前移动。
+        let mut t_start = 0.0;
+        // 光线所在的嵌套物体，光线本身只携带其中最高的优先级。
+        let mut interior = InteriorStack::default();
+
+        let mut depth = 0;
+        while depth < camera.max_depth {
+            let mut rec = HitRecord::default();
+            let ray_t = Interval::new(t_start + 0.001, rtweekend::INFINITY);
+            let hit = world.hit(&ray, &ray_t, &mut rec);
+            throughput =
+                throughput * free_flight_weight(camera, &ray, &ray_t, hit.then_some(rec.t));
+            if !hit {
+                let mut background = spectral(camera.background_value(ray.direction()), &ray);
+                if let (Some(bsdf_pdf), Some(lights)) = (bsdf_pdf, lights) {
+                    let light_pdf = lights.pdf_value(ray.origin(), ray.direction());
+                    background *= pdf::power_heuristic(bsdf_pdf, light_pdf);
+                }
+                radiance += throughput * background;
+                break;
+            }
+            let Some(mat) = rec.mat.clone() else {
+                break;
+            };
+
+            // 嵌套物体内的这一段按光线实际所在的物体吸收。与优先级更高的物体重叠处的表面被跳过。
+            throughput = throughput * interior.absorption(&ray, t_start, &rec);
+            if let Some(next) = interior.advance(&ray, &mut rec) {
+                ray = next;
+                t_start = rec.t;
+                continue;
+            }
+
+            // 上一次反弹的光源采样也可能得到这个方向，按幂启发式与它分配权重。
+            let mut emitted = mat.emitted(&ray, &rec, rec.u, rec.v, rec.p);
+            if let (Some(bsdf_pdf), Some(lights)) = (bsdf_pdf, lights) {
+                if !emitted.near_zero() {
+                    let light_pdf = lights.pdf_value(ray.origin(), ray.direction());
+                    emitted *= pdf::power_heuristic(bsdf_pdf, light_pdf);
+                }
+            }
+            radiance += throughput * emitted;
+            // 散射出去的光线已经没有深度可用，不必再采样。
+            if depth + 1 >= camera.max_depth {
+                break;
+            }
+
+            let mut srec = material::ScatterRecord::default();
+            if !mat.scatter(&ray, &rec, &mut srec) {
+                break;
+            }
+            let scattered = if srec.skip_pdf {
+                // 色散材质会为光线选定波长，其余材质沿用入射光线的波长。
+                // 谱渲染时选定的是主波长，另外两个波长到此为止。
+                let mut skip_pdf_ray = srec.skip_pdf_ray;
+                throughput = throughput * srec.attenuation;
+                if skip_pdf_ray.wavelength() == 0.0 {
+                    skip_pdf_ray = skip_pdf_ray.with_wavelength(ray.wavelength());
+                } else if ray.wavelength() == 0.0 {
+                    throughput = spectrum::terminate_secondary(throughput, ray.hero_wavelength());
+                }
+                bsdf_pdf = None;
+                skip_pdf_ray
+            } else {
+                // 直接光照：向光源采样的方向发出阴影光线。
+                if let Some(lights) = lights {
+                    let light_pdf = HittablePdf::new(Arc::clone(lights), rec.p);
+                    let shadow_ray = Ray::new_with_time(rec.p, light_pdf.generate(), ray.time())
+                        .with_wavelength(ray.wavelength())
+                        .with_hero_wavelength(ray.hero_wavelength())
+                        .with_interior_priority(interior.priority());
+                    radiance += throughput
+                        * self.direct_light(
+                            camera,
+                            &ray,
+                            &rec,
+                            &mat,
+                            &srec,
+                            &shadow_ray,
+                            light_pdf.value(shadow_ray.direction()),
+                            world,
+                        );
+                }
+
+                for light in camera.analytic_lights.iter() {
+                    radiance +=
+                        throughput * analytic_light(&ray, &rec, &mat, &srec, light.as_ref(), world);
+                }
+
+                // 间接光照：按材质采样下一个方向。
+                let scattered = Ray::new_with_time(rec.p, srec.pdf.generate(), ray.time())
+                    .with_wavelength(ray.wavelength());
+                let pdf = srec.pdf.value(scattered.direction());
+                if pdf <= 0.0 {
+                    break;
+                }
+                let bsdf_cos = mat.bsdf_cos(&ray, &rec, &srec, &scattered);
+                if bsdf_cos.near_zero() {
+                    break;
+                }
+                throughput = throughput * bsdf_cos / pdf;
+                bsdf_pdf = Some(pdf);
+                scattered
+            };
+
+            // 穿过嵌套物体的表面时更新内部栈。
+            let scattered = interior
+                .follow(&rec, scattered)
+                .with_hero_wavelength(ray.hero_wavelength());
+
+            let bounce = bounce_type(&rec, &srec, &scattered) as usize;
+            bounces[bounce] += 1;
+            let max_bounces = [
+                camera.max_diffuse_depth,
+                camera.max_specular_depth,
+                camera.max_transmission_depth,
+                camera.max_volume_depth,
+            ];
+            if bounces[bounce] > max_bounces[bounce] {
+                break;
+            }
+
+            // 俄罗斯轮盘赌：吞吐量小的路径以较大的概率终止，存活的路径按存活概率放大。
+            if depth + 1 >= camera.rr_depth {
+                let survive = throughput
+                    .x()
+                    .max(throughput.y())
+                    .max(throughput.z())
+                    .min(1.0);
+                if rtweekend::random_double() >= survive {
+                    break;
+                }
+                throughput /= survive;
+            }
+
+            ray = scattered;
+            t_start = 0.0;
+            depth += 1;
+        }
+
+        spectrum::to_linear_srgb(radiance, r.hero_wavelength())
+    }
+}
+
+impl PathTracer {
+    // 沿光源采样的方向 shadow_ray 得到的直接光照。
+    #[allow(clippy::too_many_arguments)]
+    fn direct_light(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        rec: &HitRecord,
+        mat: &Arc<dyn material::Material>,
+        srec: &material::ScatterRecord,
+        shadow_ray: &Ray,
+        light_pdf: f64,
+        world: &Arc<dyn Hittable>,
+    ) -> Color {
+        if light_pdf <= 0.0 {
+            return Color::default();
+        }
+        let bsdf_cos = mat.bsdf_cos(r, rec, srec, shadow_ray);
+        if bsdf_cos.near_zero() {
+            return Color::default();
+        }
+
+        let incident = incident_light(camera, r, shadow_ray, world);
+        if incident.near_zero() {
+            return Color::default();
+        }
+
+        let weight = pdf::power_heuristic(light_pdf, srec.pdf.value(shadow_ray.direction()));
+        weight * bsdf_cos * incident / light_pdf
+    }
+}
+
+// 沿阴影光线到达的光：命中的第一个表面的自发光，被遮挡时通常为零；没有被遮挡的
+// 环境光方向看到的是背景。到光源之间的参与介质使光衰减。结果按光线 r 的波长给出。
+pub fn incident_light(
+    camera: &Camera,
+    r: &Ray,
+    shadow_ray: &Ray,
+    world: &Arc<dyn Hittable>,
+) -> Color {
+    let mut light_rec = HitRecord::default();
+    let (emitted, distance) = if !world.shadow_hit(
+        shadow_ray,
+        &Interval::new(0.001, rtweekend::INFINITY),
+        &mut light_rec,
+    ) {
+        (
+            spectral(camera.background_value(shadow_ray.direction()), r),
+            rtweekend::INFINITY,
+        )
+    } else if let Some(light_mat) = light_rec.mat.clone() {
+        let emitted = light_mat.emitted(
+            shadow_ray,
+            &light_rec,
+            light_rec.u,
+            light_rec.v,
+            light_rec.p,
+        );
+        (emitted, light_rec.t)
+    } else {
+        return Color::default();
+    };
+    if emitted.near_zero() {
+        return Color::default();
+    }
+    emitted * world.transmittance(shadow_ray, &Interval::new(0.001, distance))
+}
+
+// 有色介质每次随机选择一个通道采样自由程，光线没有碰撞地走过一段的概率是各通道透射率的平均值，
+// 这一段按 透射率 / 平均透射率 加权（谱 MIS），碰撞点上其余的权重由介质给出。
+// ray_t 为求交的区间，t_hit 为命中的位置，没有命中时这一段延伸到 ray_t 的终点。
+pub fn free_flight_weight(camera: &Camera, r: &Ray, ray_t: &Interval, t_hit: Option<f64>) -> Color {
+    let ray_t = Interval::new(ray_t.min, t_hit.unwrap_or(ray_t.max));
+    let mut weight = Color::one();
+    for medium in camera.chromatic_media.objects.iter() {
+        let transmittance = medium.transmittance(r, &ray_t);
+        let average = (transmittance.x() + transmittance.y() + transmittance.z()) / 3.0;
+        if average > 0.0 {
+            weight = weight * transmittance / average;
+        }
+    }
+    weight
+}
+
+pub fn bounce_type(rec: &HitRecord, srec: &material::ScatterRecord, scattered: &Ray) -> Bounce {
+    if srec.volume {
+        Bounce::Volume
+    } else if vec3::dot(scattered.direction(), rec.geom_normal) < 0.0 {
+        Bounce::Transmission
+    } else if srec.skip_pdf {
+        Bounce::Specular
+    } else {
+        Bounce::Diffuse
+    }
+}
+
+// 解析光源不能被命中，只有这一种采样方式，不需要多重重要性采样的权重。
+pub fn analytic_light(
+    r: &Ray,
+    rec: &HitRecord,
+    mat: &Arc<dyn material::Material>,
+    srec: &material::ScatterRecord,
+    light: &dyn AnalyticLight,
+    world: &Arc<dyn Hittable>,
+) -> Color {
+    let Some(sample) = light.sample(rec.p, r.hero_wavelength()) else {
+        return Color::default();
+    };
+    let shadow_ray = Ray::new_with_time(rec.p, sample.direction, r.time())
+        .with_wavelength(r.wavelength())
+        .with_hero_wavelength(r.hero_wavelength())
+        .with_interior_priority(r.interior_priority());
+    let bsdf_cos = mat.bsdf_cos(r, rec, srec, &shadow_ray);
+    if bsdf_cos.near_zero() {
+        return Color::default();
+    }
+
+    let shadow_t = Interval::new(0.001, sample.distance - 0.001);
+    let mut shadow_rec = HitRecord::default();
+    if world.shadow_hit(&shadow_ray, &shadow_t, &mut shadow_rec) {
+        return Color::default();
+    }
+    bsdf_cos * sample.irradiance * world.transmittance(&shadow_ray, &shadow_t)
+}
+
+// 让命中记录的法线朝向方向为 direction 的入射光线所在的一侧，与 set_face_normal 的约定相同。
+pub fn facing(rec: &HitRecord, direction: Vec3) -> HitRecord {
+    let mut rec = rec.clone();
+    if vec3::dot(direction, rec.geom_normal) > 0.0 {
+        rec.front_face = !rec.front_face;
+        rec.normal = -rec.normal;
+        rec.geom_normal = -rec.geom_normal;
+    }
+    rec
+}
+
+pub fn outward_normal(rec: &HitRecord) -> Vec3 {
+    if rec.front_face {
+        rec.geom_normal
+    } else {
+        -rec.geom_normal
+    }
+}
+
+// 发光表面上 rec 处的一点向 direction 发出的辐亮度，r 给出时间和主波长。
+// 光源子路径和光子都从这样的点出发。
+pub fn emitted_toward(rec: &HitRecord, r: &Ray, direction: Vec3) -> Color {
+    let Some(mat) = &rec.mat else {
+        return Color::default();
+    };
+    let r = Ray::new_with_time(rec.p + direction, -direction, r.time())
+        .with_hero_wavelength(r.hero_wavelength());
+    let rec = facing(rec, r.direction());
+    mat.emitted(&r, &rec, rec.u, rec.v, rec.p)
+}
+
+// 光源是否也从背面发光，决定发射方向在一侧还是两侧的半球上采样。
+fn two_sided(rec: &HitRecord, r: &Ray) -> bool {
+    !emitted_toward(rec, r, -outward_normal(rec)).near_zero()
+}
+
+// 按余弦分布选择发射的方向，两面发光的光源在两侧各取一半。
+pub fn sample_emission_direction(rec: &HitRecord, r: &Ray) -> Vec3 {
+    let direction = Onb::new_from_w(outward_normal(rec)).local_v(vec3::random_cosine_direction());
+    if two_sided(rec, r) && rtweekend::random_double() < 0.5 {
+        -direction
+    } else {
+        direction
+    }
+}
+
+// sample_emission_direction 选到 direction 的概率密度（按立体角）。
+pub fn emission_direction_pdf(rec: &HitRecord, r: &Ray, direction: Vec3) -> f64 {
+    let cos_theta = vec3::dot(vec3::unit_vector(direction), outward_normal(rec));
+    if two_sided(rec, r) {
+        cos_theta.abs() / (2.0 * rtweekend::PI)
+    } else {
+        cos_theta.max(0.0) / rtweekend::PI
+    }
+}
+
+// 背景给出的是 RGB 颜色，谱渲染时上采样为光线的几个波长上的值。材质、光源和介质
+// 在内部上采样各自的输入，返回的已经是这几个波长上的值。
+pub fn spectral(c: Color, r: &Ray) -> Color {
+    spectrum::from_rgb(c, r.hero_wavelength())
+}
+
+// 相机一侧的光线 a 与光源一侧的光线 b 相连时的通道权重。谱渲染时两边共用相机的主波长，
+// 都选定了波长时两边各乘了一次 3，这里除掉一次。RGB 渲染时两边独立选择通道，
+// 只有通道相同时乘积不为 0，期望已经正确。
+pub fn wavelength_factor(a: &Ray, b: &Ray) -> f64 {
+    if a.hero_wavelength() > 0.0 && a.wavelength() > 0.0 && b.wavelength() > 0.0 {
+        1.0 / 3.0
+    } else {
+        1.0
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::color::Color;
+use super::hittable::HitRecord;
+use super::ray::Ray;
+use super::spectrum;
+use super::vec3;
+
+// 光线最多同时位于几层嵌套的物体之内，超过时不再记录更深的一层。
+const MAX_NESTING: usize = 4;
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+// 为设置了优先级的物体分配一个编号，材质被复制后编号不变，用来在内部栈中识别它。
+pub fn next_id() -> u32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// 带优先级的嵌套物体的内部。几个物体重叠时，光线所在的区域属于优先级最高的那个，
+// 优先级较低的物体在重叠部分的表面是虚假的交点，光线直接穿过。
+#[derive(Debug, Copy, Clone, Default)]
+pub struct NestedMedium {
+    pub id: u32,
+    pub priority: u32,
+    pub ior: f64,
+    // 内部每单位长度的吸收系数。
+    pub absorption: Color,
+    // 没有设置优先级的吸收物体也记录在栈中，但只用来计算吸收，不参与优先级的比较。
+    pub prioritized: bool,
+}
+
+// 光线当前位于哪些嵌套物体之内，按进入的先后排列。
+#[derive(Debug, Copy, Clone, Default)]
+pub struct InteriorStack {
+    entries: [NestedMedium; MAX_NESTING],
+    len: usize,
+}
+
+impl InteriorStack {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.entries[..self.len].iter().any(|entry| entry.id == id)
+    }
+
+    // 光线实际所在的物体：优先级最高的一个，相同时取最后进入的。
+    pub fn top(&self) -> Option<NestedMedium> {
+        self.top_excluding(0)
+    }
+
+    // 不考虑编号为 id 的物体时光线所在的物体，也就是从它的表面穿出后的一侧。
+    pub fn top_excluding(&self, id: u32) -> Option<NestedMedium> {
+        self.entries[..self.len]
+            .iter()
+            .filter(|entry| entry.prioritized && entry.id != id)
+            .fold(None, |top: Option<NestedMedium>, entry| match top {
+                Some(top) if top.priority > entry.priority => Some(top),
+                _ => Some(*entry),
+            })
+    }
+
+    // 光线实际所在的物体的优先级，光线只携带这一项。
+    pub fn priority(&self) -> Option<u32> {
+        self.top().map(|top| top.priority)
+    }
+
+    // 外侧的折射率，不在任何嵌套物体之内时为空气。
+    pub fn outside_ior(&self, id: u32) -> f64 {
+        self.top_excluding(id).map_or(1.0, |entry| entry.ior)
+    }
+
+    // 在这个表面上的交点是否是虚假的：进入的物体被优先级更高的物体包含，
+    // 或者离开的物体并不是光线实际所在的物体。
+    pub fn is_false_hit(&self, medium: &NestedMedium, front_face: bool) -> bool {
+        if !medium.prioritized {
+            return false;
+        }
+        let top = if front_face {
+            self.top()
+        } else if self.contains(medium.id) {
+            self.top_excluding(medium.id)
+        } else {
+            None
+        };
+        top.is_some_and(|top| top.priority > medium.priority)
+    }
+
+    // 在光线所在的物体内走过 distance 后剩余的比例，不在吸收的物体内时为 None。
+    // 光线所在的物体是优先级最高的物体和没有优先级的物体中最后进入的一个。
+    // 谱渲染时吸收系数上采样到主波长为 hero 的几个波长上。
+    pub fn transmittance(&self, distance: f64, hero: f64) -> Option<Color> {
+        let top = self.top();
+        let absorption = self.entries[..self.len]
+            .iter()
+            .rev()
+            .find(|entry| !entry.prioritized || top.is_some_and(|top| top.id == entry.id))?
+            .absorption;
+        if absorption.near_zero() {
+            return None;
+        }
+        let absorption = spectrum::from_rgb(absorption, hero);
+        Some(Color::new(
+            (-absorption.x() * distance).exp(),
+            (-absorption.y() * distance).exp(),
+            (-absorption.z() * distance).exp(),
+        ))
+    }
+
+    pub fn entered(mut self, medium: NestedMedium) -> Self {
+        if self.len < MAX_NESTING && !self.contains(medium.id) {
+            self.entries[self.len] = medium;
+            self.len += 1;
+        }
+        self
+    }
+
+    pub fn exited(mut self, id: u32) -> Self {
+        if let Some(i) = self.entries[..self.len]
+            .iter()
+            .rposition(|entry| entry.id == id)
+        {
+            self.entries.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+        }
+        self
+    }
+
+    // 光线 ray 从 t_start 走到 rec 的这一段在光线所在的物体内被吸收后剩余的比例。
+    pub fn absorption(&self, ray: &Ray, t_start: f64, rec: &HitRecord) -> Color {
+        let distance = (rec.t - t_start) * ray.direction().length();
+        self.transmittance(distance, ray.hero_wavelength())
+            .unwrap_or(Color::one())
+    }
+
+    // 光线 ray 命中 rec 时更新内部栈。交点是虚假的时返回带有新优先级的同一条光线，
+    // 积分器从 rec.t 继续求交；否则为 rec 填入外侧的折射率，返回 None。
+    pub fn advance(&mut self, ray: &Ray, rec: &mut HitRecord) -> Option<Ray> {
+        let nested = rec.mat.as_ref()?.nested_medium(ray.wavelength())?;
+        if self.is_false_hit(&nested, rec.front_face) {
+            self.cross(nested, rec.front_face);
+            return Some(ray.with_interior_priority(self.priority()));
+        }
+        rec.outside_ior = Some(self.outside_ior(nested.id));
+        None
+    }
+
+    // 在 rec 处散射出的光线 scattered 穿过嵌套物体的表面时更新内部栈，返回带有新优先级的光线。
+    pub fn follow(&mut self, rec: &HitRecord, scattered: Ray) -> Ray {
+        let nested = rec
+            .mat
+            .as_ref()
+            .and_then(|mat| mat.nested_medium(scattered.wavelength()));
+        if let Some(nested) = nested {
+            if vec3::dot(scattered.direction(), rec.geom_normal) < 0.0 {
+                self.cross(nested, rec.front_face);
+            }
+        }
+        scattered.with_interior_priority(self.priority())
+    }
+
+    // 从正面进入或从背面离开 medium。
+    fn cross(&mut self, medium: NestedMedium, front_face: bool) {
+        *self = if front_face {
+            self.entered(medium)
+        } else {
+            self.exited(medium.id)
+        };
+    }
+}
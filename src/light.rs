@@ -0,0 +1,616 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::color::Color;
+use super::hittable::{self, HitRecord, Hittable};
+use super::hittable_list::HittableList;
+use super::interval::Interval;
+use super::onb::Onb;
+use super::ray::Ray;
+use super::rtweekend;
+use super::spectrum;
+use super::vec3::{self, Point3, Vec3};
+
+// Walker 别名表：O(1) 时间按离散分布抽样。
+pub struct AliasTable {
+    pmf: Vec<f64>,
+    prob: Vec<f64>,
+    alias: Vec<usize>,
+}
+
+impl AliasTable {
+    // 权重之和为零时（例如所有光源的功率都无法估计）退化为均匀分布。
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let total: f64 = weights.iter().map(|w| w.max(0.0)).sum();
+        let pmf: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|w| w.max(0.0) / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        };
+
+        let mut scaled: Vec<f64> = pmf.iter().map(|p| p * n as f64).collect();
+        let mut prob = vec![1.0; n];
+        let mut alias: Vec<usize> = (0..n).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| scaled[i] < 1.0);
+
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            prob[s] = scaled[s];
+            alias[s] = l;
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // 剩下的项只是因为舍入误差没有恰好等于 1，保持 prob = 1。
+
+        Self { pmf, prob, alias }
+    }
+
+    pub fn sample(&self) -> usize {
+        let n = self.prob.len();
+        let x = rtweekend::random_double() * n as f64;
+        let i = (x as usize).min(n - 1);
+        if x - (i as f64) < self.prob[i] {
+            i
+        } else {
+            self.alias[i]
+        }
+    }
+
+    pub fn pmf(&self, i: usize) -> f64 {
+        self.pmf[i]
+    }
+}
+
+// 按发射功率选择光源。与 HittableList 的均匀选择相比，大而亮的光源得到更多样本。
+pub struct LightSampler {
+    lights: HittableList,
+    table: AliasTable,
+    // 发射光源子路径时按 emission_power 选择光源，跳过环境光等不能这样采样的光源。
+    emission_table: AliasTable,
+}
+
+impl LightSampler {
+    pub fn new(lights: HittableList) -> Self {
+        let power: Vec<f64> = lights.objects.iter().map(|light| light.power()).collect();
+        let emission_power: Vec<f64> = lights
+            .objects
+            .iter()
+            .map(|light| light.emission_power())
+            .collect();
+        Self {
+            table: AliasTable::new(&power),
+            emission_table: AliasTable::new(&emission_power),
+            lights,
+        }
+    }
+}
+
+impl Hittable for LightSampler {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        self.lights.hit(r, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.lights.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.lights
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, light)| self.table.pmf(i) * light.pdf_value(origin, direction))
+            .sum()
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        if self.lights.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        self.lights.objects[self.table.sample()].random(origin)
+    }
+
+    fn power(&self) -> f64 {
+        self.lights.power()
+    }
+
+    fn emission_power(&self) -> f64 {
+        self.lights.emission_power()
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        if self.lights.objects.is_empty() {
+            return 0.0;
+        }
+        let i = self.emission_table.sample();
+        self.emission_table.pmf(i) * self.lights.objects[i].sample_emission(rec)
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        self.lights
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, light)| self.emission_table.pmf(i) * light.emission_pdf(r, rec))
+            .sum()
+    }
+}
+
+enum LightNode {
+    Leaf(Arc<dyn Hittable>),
+    Interior(Box<LightBvh>, Box<LightBvh>),
+}
+
+// 光源的空间层次结构，适合成千上万个发光三角形。从根节点向下走，按子节点相对于
+// 着色点的重要性（功率除以距离的平方）选择分支，近处的光源得到更多样本。
+pub struct LightBvh {
+    node: LightNode,
+    power: f64,
+    emission_power: f64,
+    bbox: Aabb,
+}
+
+impl LightBvh {
+    pub fn new(lights: HittableList) -> Self {
+        Self::new_with_lights(lights.objects)
+    }
+
+    fn new_with_lights(mut lights: Vec<Arc<dyn Hittable>>) -> Self {
+        if lights.len() <= 1 {
+            let light = lights
+                .pop()
+                .unwrap_or_else(|| Arc::new(HittableList::default()));
+            return Self {
+                power: light.power(),
+                emission_power: light.emission_power(),
+                bbox: light.bounding_box().clone(),
+                node: LightNode::Leaf(light),
+            };
+        }
+
+        // 按包围盒中心在最长轴上的位置排序后对半分。
+        let first = center(lights[0].bounding_box());
+        let mut centroids = Aabb::new_with_point(&first, &first);
+        for light in lights.iter() {
+            let c = center(light.bounding_box());
+            centroids = Aabb::new_with_box(&centroids, &Aabb::new_with_point(&c, &c));
+        }
+        let axis = centroids.longest_axis();
+        lights.sort_by(|a, b| {
+            center(a.bounding_box())[axis].total_cmp(&center(b.bounding_box())[axis])
+        });
+
+        let right = lights.split_off(lights.len() / 2);
+        let left = Self::new_with_lights(lights);
+        let right = Self::new_with_lights(right);
+        Self {
+            power: left.power + right.power,
+            emission_power: left.emission_power + right.emission_power,
+            bbox: Aabb::new_with_box(&left.bbox, &right.bbox),
+            node: LightNode::Interior(Box::new(left), Box::new(right)),
+        }
+    }
+
+    fn importance(&self, origin: Point3) -> f64 {
+        // 着色点在包围盒内部或很近时距离取包围盒的半径，避免重要性发散。
+        let half_diagonal =
+            0.5 * Vec3::new(self.bbox.x.size(), self.bbox.y.size(), self.bbox.z.size()).length();
+        let distance_squared = (center(&self.bbox) - origin)
+            .length_squared()
+            .max(half_diagonal * half_diagonal);
+        self.power / distance_squared
+    }
+
+    // 光源子路径的起点与着色点无关，按 emission_power 选择子树。
+    fn left_emission_probability(left: &LightBvh, right: &LightBvh) -> f64 {
+        let total = left.emission_power + right.emission_power;
+        if total > 0.0 {
+            left.emission_power / total
+        } else {
+            0.5
+        }
+    }
+
+    // 从 origin 出发时选择左子树的概率。random 和 pdf_value 必须使用同一个概率。
+    fn left_probability(left: &LightBvh, right: &LightBvh, origin: Point3) -> f64 {
+        let l = left.importance(origin);
+        let r = right.importance(origin);
+        if l + r > 0.0 { l / (l + r) } else { 0.5 }
+    }
+}
+
+fn center(bbox: &Aabb) -> Point3 {
+    Point3::new(
+        0.5 * (bbox.x.min + bbox.x.max),
+        0.5 * (bbox.y.min + bbox.y.max),
+        0.5 * (bbox.z.min + bbox.z.max),
+    )
+}
+
+impl Hittable for LightBvh {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        match &self.node {
+            LightNode::Leaf(light) => light.hit(r, ray_t, rec),
+            LightNode::Interior(left, right) => {
+                let mut ray_t = ray_t.clone();
+                if !self.bbox.hit(r, &mut ray_t) {
+                    return false;
+                }
+                let hit_left = left.hit(r, &ray_t, rec);
+                let ray_t = Interval::new(ray_t.min, if hit_left { rec.t } else { ray_t.max });
+                let hit_right = right.hit(r, &ray_t, rec);
+                hit_left || hit_right
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        match &self.node {
+            LightNode::Leaf(light) => light.pdf_value(origin, direction),
+            LightNode::Interior(left, right) => {
+                // 这个方向没有穿过包围盒时，子树中的光源都不可能产生它。
+                let mut ray_t = Interval::new(0.0, rtweekend::INFINITY);
+                if !self.bbox.hit(&Ray::new(origin, direction), &mut ray_t) {
+                    return 0.0;
+                }
+                let p = Self::left_probability(left, right, origin);
+                p * left.pdf_value(origin, direction)
+                    + (1.0 - p) * right.pdf_value(origin, direction)
+            }
+        }
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        let mut node = self;
+        loop {
+            match &node.node {
+                LightNode::Leaf(light) => return light.random(origin),
+                LightNode::Interior(left, right) => {
+                    let p = Self::left_probability(left, right, origin);
+                    node = if rtweekend::random_double() < p {
+                        left
+                    } else {
+                        right
+                    };
+                }
+            }
+        }
+    }
+
+    fn power(&self) -> f64 {
+        self.power
+    }
+
+    fn emission_power(&self) -> f64 {
+        self.emission_power
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let mut node = self;
+        let mut pmf = 1.0;
+        loop {
+            match &node.node {
+                LightNode::Leaf(light) => return pmf * light.sample_emission(rec),
+                LightNode::Interior(left, right) => {
+                    let p = Self::left_emission_probability(left, right);
+                    (node, pmf) = if rtweekend::random_double() < p {
+                        (left, pmf * p)
+                    } else {
+                        (right, pmf * (1.0 - p))
+                    };
+                }
+            }
+        }
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        match &self.node {
+            LightNode::Leaf(light) => light.emission_pdf(r, rec),
+            LightNode::Interior(left, right) => {
+                // 命中点不在包围盒内时，子树中的光源都不可能取到它。
+                let mut ray_t = hittable::emission_interval(rec.t);
+                if !self.bbox.hit(r, &mut ray_t) {
+                    return 0.0;
+                }
+                let p = Self::left_emission_probability(left, right);
+                p * left.emission_pdf(r, rec) + (1.0 - p) * right.emission_pdf(r, rec)
+            }
+        }
+    }
+}
+
+// 解析光源对一个着色点的采样结果。
+pub struct LightSample {
+    // 指向光源的单位向量和到光源的距离，用于阴影测试。
+    pub direction: Vec3,
+    pub distance: f64,
+    // 光线垂直入射时在着色点产生的辐照度，余弦项由材质的 bsdf_cos 给出。
+    pub irradiance: Color,
+}
+
+// 点光源、聚光灯和平行光这类没有几何形状的光源。光线不会命中它们，
+// 相机在每次非镜面反弹时向所有解析光源发出阴影光线。hero 为谱渲染的主波长，RGB 渲染时为 0。
+pub trait AnalyticLight: Send + Sync {
+    fn sample(&self, p: Point3, hero: f64) -> Option<LightSample>;
+}
+
+// 在以 center 为中心、垂直于 p 方向的半径为 radius 的圆盘上取一点，用来产生软阴影。
+fn jitter_position(center: Point3, radius: f64, p: Point3) -> Point3 {
+    if radius <= 0.0 {
+        return center;
+    }
+    let uvw = Onb::new_from_w(p - center);
+    let disk = radius * vec3::random_in_unit_disk();
+    center + uvw.local_v(disk)
+}
+
+fn sample_position(position: Point3, p: Point3, intensity: Color) -> Option<LightSample> {
+    let to_light = position - p;
+    let distance_squared = to_light.length_squared();
+    if distance_squared <= 0.0 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    Some(LightSample {
+        direction: to_light / distance,
+        distance,
+        irradiance: intensity / distance_squared,
+    })
+}
+
+#[derive(Clone)]
+pub struct PointLight {
+    pub position: Point3,
+    // 发光强度（每单位立体角的功率）。
+    pub intensity: Color,
+    pub radius: f64,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, color: Color) -> Self {
+        Self {
+            position,
+            intensity: color,
+            radius: 0.0,
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.intensity *= strength;
+        self
+    }
+
+    // 光源的半径，大于零时阴影的边缘变软。
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+}
+
+impl AnalyticLight for PointLight {
+    fn sample(&self, p: Point3, hero: f64) -> Option<LightSample> {
+        let position = jitter_position(self.position, self.radius, p);
+        sample_position(position, p, spectrum::from_rgb(self.intensity, hero))
+    }
+}
+
+#[derive(Clone)]
+pub struct SpotLight {
+    pub position: Point3,
+    pub direction: Vec3,
+    pub intensity: Color,
+    pub radius: f64,
+    // 锥角的余弦：在 cos_inner 以内为全强度，在 cos_outer 以外为零，中间平滑过渡。
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    // angle 为光锥的半角（度），默认从 0.8 倍半角处开始衰减。
+    pub fn new(position: Point3, target: Point3, color: Color, angle: f64) -> Self {
+        Self {
+            position,
+            direction: vec3::unit_vector(target - position),
+            intensity: color,
+            radius: 0.0,
+            cos_inner: (0.8 * angle).to_radians().cos(),
+            cos_outer: angle.to_radians().cos(),
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.intensity *= strength;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    // 从 inner_angle（度）开始衰减到光锥边缘。
+    pub fn with_falloff_start(mut self, inner_angle: f64) -> Self {
+        self.cos_inner = inner_angle.to_radians().cos().max(self.cos_outer);
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl AnalyticLight for SpotLight {
+    fn sample(&self, p: Point3, hero: f64) -> Option<LightSample> {
+        let cos_theta = vec3::dot(vec3::unit_vector(p - self.position), self.direction);
+        let falloff = self.falloff(cos_theta);
+        if falloff <= 0.0 {
+            return None;
+        }
+        let position = jitter_position(self.position, self.radius, p);
+        sample_position(
+            position,
+            p,
+            falloff * spectrum::from_rgb(self.intensity, hero),
+        )
+    }
+}
+
+// 无穷远处的平行光（例如太阳），direction 指向光源。
+#[derive(Clone)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    // 垂直于光线的平面上的辐照度。
+    pub irradiance: Color,
+    cos_angle: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Color) -> Self {
+        Self {
+            direction: vec3::unit_vector(direction),
+            irradiance: color,
+            cos_angle: 1.0,
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.irradiance *= strength;
+        self
+    }
+
+    // 光源的视半径（度），大于零时阴影的边缘变软。太阳约为 0.27 度。
+    pub fn with_angle(mut self, angle: f64) -> Self {
+        self.cos_angle = angle.max(0.0).to_radians().cos();
+        self
+    }
+}
+
+impl AnalyticLight for DirectionalLight {
+    fn sample(&self, _p: Point3, hero: f64) -> Option<LightSample> {
+        let direction = if self.cos_angle < 1.0 {
+            let cos_theta = 1.0 - rtweekend::random_double() * (1.0 - self.cos_angle);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * rtweekend::PI * rtweekend::random_double();
+            Onb::new_from_w(self.direction).local_v(Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+        } else {
+            self.direction
+        };
+        Some(LightSample {
+            direction,
+            distance: rtweekend::INFINITY,
+            irradiance: spectrum::from_rgb(self.irradiance, hero),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::DiffuseLight;
+    use crate::quad::Quad;
+
+    const SAMPLES: usize = 200000;
+
+    // 位置、大小和亮度各不相同、互不重叠的几个面光源。
+    fn lights() -> HittableList {
+        let mut lights = HittableList::default();
+        for i in 0..6 {
+            let x = i as f64 * 2.0 - 5.0;
+            let size = 0.5 + 0.2 * i as f64;
+            let strength = [1.0, 4.0, 2.0, 1.0, 3.0, 6.0][i];
+            lights.add(Arc::new(Quad::new(
+                Point3::new(x, 3.0 + (i % 3) as f64, -1.0 - i as f64),
+                Vec3::new(size, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, size),
+                DiffuseLight::new_with_color(Color::new(strength, strength, strength)),
+            )));
+        }
+        lights
+    }
+
+    #[test]
+    fn alias_table_matches_weights() {
+        let weights = [1.0, 0.0, 3.0, 6.0, 0.5];
+        let total: f64 = weights.iter().sum();
+        let table = AliasTable::new(&weights);
+        let pmf_sum: f64 = (0..weights.len()).map(|i| table.pmf(i)).sum();
+        assert!((pmf_sum - 1.0).abs() < 1e-12, "pmf sums to {pmf_sum}");
+
+        let mut counts = [0usize; 5];
+        for _ in 0..SAMPLES {
+            counts[table.sample()] += 1;
+        }
+        for (i, weight) in weights.iter().enumerate() {
+            assert!((table.pmf(i) - weight / total).abs() < 1e-12);
+            let frequency = counts[i] as f64 / SAMPLES as f64;
+            assert!(
+                (frequency - table.pmf(i)).abs() < 0.005,
+                "entry {i}: sampled {frequency}, pmf {}",
+                table.pmf(i)
+            );
+        }
+
+        // 权重全为零时退化为均匀分布。
+        let uniform = AliasTable::new(&[0.0, 0.0, 0.0, 0.0]);
+        for i in 0..4 {
+            assert_eq!(uniform.pmf(i), 0.25);
+        }
+    }
+
+    // 按 sampler 的 random 采样方向时，每个光源自身的 pdf 与 sampler 的 pdf 之比的期望为 1，
+    // 即 sampler 的 pdf_value 与它的采样分布一致。
+    fn check_pdf_matches_sampling(sampler: &dyn Hittable, lights: &HittableList) {
+        for origin in [Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, -3.0)] {
+            let mut sums = vec![(0.0, 0.0); lights.objects.len()];
+            for _ in 0..SAMPLES {
+                let direction = sampler.random(origin);
+                let pdf = sampler.pdf_value(origin, direction);
+                assert!(pdf > 0.0, "sampled a direction with zero pdf");
+                for (sum, light) in sums.iter_mut().zip(lights.objects.iter()) {
+                    let ratio = light.pdf_value(origin, direction) / pdf;
+                    *sum = (sum.0 + ratio, sum.1 + ratio * ratio);
+                }
+            }
+            // 允许的误差为标准误差的 5 倍。
+            let n = SAMPLES as f64;
+            for (i, (sum, sum_squares)) in sums.iter().enumerate() {
+                let mean = sum / n;
+                let error = ((sum_squares / n - mean * mean) / n).sqrt();
+                assert!(
+                    (mean - 1.0).abs() < 5.0 * error + 1e-3,
+                    "light {i} from {origin:?}: {mean} ± {error}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn light_sampler_pdf_matches_sampling() {
+        check_pdf_matches_sampling(&LightSampler::new(lights()), &lights());
+    }
+
+    #[test]
+    fn light_bvh_pdf_matches_sampling() {
+        check_pdf_matches_sampling(&LightBvh::new(lights()), &lights());
+    }
+}
@@ -1,29 +1,45 @@
 #![allow(dead_code)]
 pub mod aabb;
+pub mod bdpt;
 pub mod bvh;
 pub mod camera;
 pub mod color;
 pub mod constant_medium;
+pub mod environment;
 pub mod hittable;
 pub mod hittable_list;
+pub mod integrator;
+pub mod interior;
 pub mod interval;
+pub mod light;
+pub mod mapping;
 pub mod material;
+pub mod microfacet;
+pub mod mlt;
 pub mod model;
 pub mod onb;
 pub mod pdf;
 pub mod perlin;
+pub mod phase;
+pub mod principled;
 pub mod quad;
 pub mod ray;
 pub mod rtw_stb_image;
 pub mod rtweekend;
+pub mod sampler;
+pub mod spectrum;
 pub mod sphere;
+pub mod sppm;
+pub mod subsurface;
 pub mod texture;
 pub mod triangle;
 pub mod vec3;
+pub mod volume;
 
 use std::sync::Arc;
 
 //use crate::model::load_model;
+use crate::bdpt::Bdpt;
 use crate::bvh::BvhNode;
 use crate::camera::Camera;
 use crate::hittable::{Hittable, RotateX, RotateY, Scale, Translate};
@@ -32,7 +48,7 @@ use crate::texture::{ImageTexture, NoiseTexture};
 use crate::vec3::{Point3, Vec3};
 use color::Color;
 use hittable_list::HittableList;
-use material::{Dielectric, DiffuseLight, Lambertian, Metal};
+use material::{Dielectric, DiffuseLight, Lambertian, Metal, ThinDielectric};
 use quad::Quad;
 use sphere::Sphere;
 
@@ -68,7 +84,7 @@ fn final_scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
         Point3::new(123.0, 554.0, 147.0),
         Vec3::new(300.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 265.0),
-        light.clone(),
+        light,
     )));
 
     let center1 = Point3::new(400.0, 400.0, 200.0);
@@ -135,15 +151,6 @@ fn final_scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
         Vec3::new(-100.0, 270.0, 395.0),
     )));*/
 
-    let mut lights = HittableList::default();
-    lights.add(Arc::new(Quad::new(
-        Point3::new(123.0, 554.0, 147.0),
-        Vec3::new(300.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 265.0),
-        light,
-    )));
-    //lights.add(boundary);
-
     let mut cam = Camera::default();
 
     cam.aspect_ratio = 1.0;
@@ -159,7 +166,7 @@ fn final_scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
 
     cam.defocus_angle = 0.0;
 
-    cam.render(Arc::new(world), Arc::new(lights));
+    cam.render(Arc::new(world));
 }
 
 fn attempt(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
@@ -218,14 +225,7 @@ fn attempt(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
         Point3::new(-0.5, 3.0, -0.5),
         Vec3::new(1.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        light_material.clone(),
-    )));
-    let mut lights = HittableList::default();
-    lights.add(Arc::new(Quad::new(
-        Point3::new(-0.5, 3.0, -0.5),
-        Vec3::new(1.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 1.0),
-        light_material.clone(),
+        light_material,
     )));
 
     let mut cam = Camera::default();
@@ -244,12 +244,11 @@ fn attempt(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
     cam.defocus_angle = 0.5;
     cam.focus_dist = (cam.lookfrom - cam.lookat).length();
 
-    cam.render(Arc::new(world), Arc::new(lights));
+    cam.render(Arc::new(world));
 }
 
 fn scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
     let mut world = HittableList::new();
-    let mut lights = HittableList::default();
 
     let ground = Lambertian::new_with_texture(ImageTexture::new("wood.jpg"));
     world.add(Arc::new(Quad::new(
@@ -279,13 +278,13 @@ fn scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
         Point3::new(-2.0, 0.0, 2.0),
         Vec3::new(0.0, 0.0, -4.0),
         Vec3::new(0.0, 4.0, 0.0),
-        Dielectric::new(1.5), //left
+        ThinDielectric::new(1.5), //left
     )));
     world.add(Arc::new(Quad::new(
         Point3::new(2.0, 0.0, -2.0),
         Vec3::new(0.0, 0.0, 4.0),
         Vec3::new(0.0, 4.0, 0.0),
-        Dielectric::new(1.5), //right
+        ThinDielectric::new(1.5), //right
     )));
     //--------------------------------------------------------------------------
     let model_material = Lambertian::new(Color::new(0.8, 0.85, 0.9));
@@ -412,13 +411,7 @@ fn scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
         Point3::new(-0.5, 3.0, -0.5),
         Vec3::new(1.0, 0.0, 0.0),
         Vec3::new(0.0, 0.0, 1.0),
-        light_material.clone(),
-    )));
-    lights.add(Arc::new(Quad::new(
-        Point3::new(-0.5, 3.0, -0.5),
-        Vec3::new(1.0, 0.0, 0.0),
-        Vec3::new(0.0, 0.0, 1.0),
-        light_material.clone(),
+        light_material,
     )));
 
     let mut cam = Camera::default();
@@ -437,7 +430,10 @@ fn scene(image_width: u32, samples_per_pixel: usize, max_depth: i32) {
     cam.defocus_angle = 0.5;
     cam.focus_dist = (cam.lookfrom - cam.lookat).length();
 
-    cam.render(Arc::new(world), Arc::new(lights));
+    // 光源透过两侧的玻璃照亮场景，双向路径追踪能找到这些焦散。
+    cam.integrator = Arc::new(Bdpt);
+
+    cam.render(Arc::new(world));
 }
 
 fn main() {
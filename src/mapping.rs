@@ -0,0 +1,362 @@
+use super::color::Color;
+use super::hittable::HitRecord;
+use super::interior::NestedMedium;
+use super::material::{DiffuseLight, Material, ScatterRecord};
+use super::onb::Onb;
+use super::ray::Ray;
+use super::texture::{self, Texture};
+use super::vec3::{self, Point3, Vec3};
+
+// 对着色法线的扰动都以材质包装器的形式实现：先修改命中记录中的 normal，
+// 再交给内部材质处理。几何法线 geom_normal 保持不变。
+
+fn tangent_frame(rec: &HitRecord) -> (Vec3, Vec3) {
+    // 返回与着色法线正交的单位切线和副切线，副切线与 dpdv 同向以保留纹理的手性。
+    let n = rec.normal;
+    if rec.dpdu.near_zero() {
+        let uvw = Onb::new_from_w(n);
+        return (uvw.u(), uvw.v());
+    }
+    let t = rec.dpdu - vec3::dot(rec.dpdu, n) * n;
+    if t.near_zero() {
+        let uvw = Onb::new_from_w(n);
+        return (uvw.u(), uvw.v());
+    }
+    let t = vec3::unit_vector(t);
+    let b = vec3::cross(n, t);
+    if vec3::dot(b, rec.dpdv) < 0.0 {
+        (t, -b)
+    } else {
+        (t, b)
+    }
+}
+
+fn with_normal(rec: &HitRecord, normal: Vec3) -> HitRecord {
+    let mut perturbed = rec.clone();
+    // 扰动后的法线不能翻到几何表面的另一侧，否则退回原来的着色法线。
+    if vec3::dot(normal, rec.geom_normal) > 0.0 {
+        perturbed.normal = normal;
+    }
+    perturbed
+}
+
+#[derive(Clone)]
+pub struct NormalMap<M: Material, T: Texture> {
+    material: M,
+    normal_map: T,
+    strength: f64,
+}
+
+impl<M: Material, T: Texture> NormalMap<M, T> {
+    // normal_map 应为线性色彩空间的切线空间法线贴图（OpenGL 约定，绿色通道朝 +v）。
+    pub fn new(material: M, normal_map: T) -> Self {
+        Self::new_with_strength(material, normal_map, 1.0)
+    }
+
+    pub fn new_with_strength(material: M, normal_map: T, strength: f64) -> Self {
+        Self {
+            material,
+            normal_map,
+            strength,
+        }
+    }
+
+    fn perturb(&self, rec: &HitRecord) -> HitRecord {
+        let c = self.normal_map.value(rec.u, rec.v, rec.p);
+        let local = 2.0 * c - Vec3::one();
+        let local = Vec3::new(
+            self.strength * local.x(),
+            self.strength * local.y(),
+            local.z(),
+        );
+        if local.near_zero() {
+            return rec.clone();
+        }
+        // 法线贴图是为表面的外侧编写的，命中背面时切线空间随法线一起翻转。
+        let (t, b) = tangent_frame(rec);
+        let (t, b) = if rec.front_face { (t, b) } else { (-t, -b) };
+        let normal = vec3::unit_vector(local.x() * t + local.y() * b + local.z() * rec.normal);
+        with_normal(rec, normal)
+    }
+}
+
+impl<M: Material, T: Texture> Material for NormalMap<M, T> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.material.scatter(r_in, &self.perturb(rec), srec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.material.emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material
+            .scattering_pdf(r_in, &self.perturb(rec), scattered)
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.material
+            .bsdf_cos(r_in, &self.perturb(rec), srec, scattered)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.material.alpha(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        self.material.emitted_power()
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        self.material.nested_medium(wavelength)
+    }
+}
+
+#[derive(Clone)]
+pub struct BumpMap<M: Material, T: Texture> {
+    material: M,
+    height: T,
+    scale: f64,
+}
+
+impl<M: Material, T: Texture> BumpMap<M, T> {
+    // 高度取纹理颜色三个分量的平均值，乘以 scale 作为沿法线的位移。
+    // 纹理可以依赖 (u, v)（如图像）或 p（如 NoiseTexture），求差分时两者同时移动。
+    pub fn new(material: M, height: T, scale: f64) -> Self {
+        Self {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn displacement(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.scale * texture::scalar_value(&self.height, u, v, p)
+    }
+
+    fn perturb(&self, rec: &HitRecord) -> HitRecord {
+        let (dpdu, dpdv) = if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+            let uvw = Onb::new_from_w(rec.normal);
+            (uvw.u(), uvw.v())
+        } else {
+            (rec.dpdu, rec.dpdv)
+        };
+
+        let du = 0.0005;
+        let dv = 0.0005;
+        let d = self.displacement(rec.u, rec.v, rec.p);
+        let d_u = self.displacement(rec.u + du, rec.v, rec.p + du * dpdu);
+        let d_v = self.displacement(rec.u, rec.v + dv, rec.p + dv * dpdv);
+
+        // 位移沿外法线方向；忽略法线自身随 (u, v) 的变化，只保留高度梯度项。
+        let n = if rec.front_face {
+            rec.normal
+        } else {
+            -rec.normal
+        };
+        let bumped_dpdu = dpdu + ((d_u - d) / du) * n;
+        let bumped_dpdv = dpdv + ((d_v - d) / dv) * n;
+        let normal = vec3::cross(bumped_dpdu, bumped_dpdv);
+        if normal.near_zero() {
+            return rec.clone();
+        }
+        // 按未扰动切线的手性确定朝向，再翻到命中的一侧。
+        let flip = (vec3::dot(vec3::cross(dpdu, dpdv), n) < 0.0) == rec.front_face;
+        let normal = vec3::unit_vector(normal);
+        with_normal(rec, if flip { -normal } else { normal })
+    }
+}
+
+impl<M: Material, T: Texture> Material for BumpMap<M, T> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.material.scatter(r_in, &self.perturb(rec), srec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.material.emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material
+            .scattering_pdf(r_in, &self.perturb(rec), scattered)
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.material
+            .bsdf_cos(r_in, &self.perturb(rec), srec, scattered)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.material.alpha(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        self.material.emitted_power()
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        self.material.nested_medium(wavelength)
+    }
+}
+
+#[derive(Clone)]
+pub struct AlphaMap<M: Material, T: Texture> {
+    material: M,
+    mask: T,
+    from_channel: bool,
+    cutoff: Option<f64>,
+}
+
+impl<M: Material, T: Texture> AlphaMap<M, T> {
+    // 使用纹理的 alpha 通道（例如带透明度的 PNG）作为不透明度，部分透明按概率穿过。
+    pub fn new(material: M, mask: T) -> Self {
+        Self {
+            material,
+            mask,
+            from_channel: true,
+            cutoff: None,
+        }
+    }
+
+    // 使用纹理颜色的平均值作为不透明度，对应 MTL 中的灰度 map_d 贴图。
+    pub fn new_with_mask(material: M, mask: T) -> Self {
+        Self {
+            material,
+            mask,
+            from_channel: false,
+            cutoff: None,
+        }
+    }
+
+    // 镂空模式：不透明度低于 cutoff 的地方完全透明，其余完全不透明。适合树叶等硬边缘。
+    pub fn with_cutoff(mut self, cutoff: f64) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
+}
+
+impl<M: Material, T: Texture> Material for AlphaMap<M, T> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.material.scatter(r_in, rec, srec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.material.emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.material.bsdf_cos(r_in, rec, srec, scattered)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        let alpha = if self.from_channel {
+            self.mask.alpha(u, v, p)
+        } else {
+            texture::scalar_value(&self.mask, u, v, p)
+        } * self.material.alpha(u, v, p);
+
+        match self.cutoff {
+            Some(cutoff) if alpha < cutoff => 0.0,
+            Some(_) => 1.0,
+            None => alpha,
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        self.material.emitted_power()
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        self.material.nested_medium(wavelength)
+    }
+}
+
+// 给任意材质加上自发光，发射部分由一个 DiffuseLight 描述（可以是 map_Ke 这样的贴图）。
+pub struct EmissionMap<M: Material, T: Texture> {
+    material: M,
+    light: DiffuseLight<T>,
+}
+
+impl<M: Material, T: Texture> EmissionMap<M, T> {
+    pub fn new(material: M, emission: T) -> Self {
+        Self::new_with_light(material, DiffuseLight::new(emission))
+    }
+
+    pub fn new_with_light(material: M, light: DiffuseLight<T>) -> Self {
+        Self { material, light }
+    }
+}
+
+impl<M: Material, T: Texture> Material for EmissionMap<M, T> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.material.scatter(r_in, rec, srec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.light.emitted(r_in, rec, u, v, p) + self.material.emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.material.bsdf_cos(r_in, rec, srec, scattered)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.material.alpha(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.light.is_emissive() || self.material.is_emissive()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        self.light.emitted_power() + self.material.emitted_power()
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        self.material.nested_medium(wavelength)
+    }
+}
@@ -1,12 +1,18 @@
-use super::color::Color;
+use super::color::{self, Color};
 use super::hittable::HitRecord;
-use super::pdf::{CosinePdf, SpherePdf};
-//use super::onb;
+use super::interior::{self, NestedMedium};
+use super::microfacet::{
+    self, MicrofacetDielectric, MicrofacetDielectricPdf, MicrofacetReflectionPdf, TrowbridgeReitz,
+};
+use super::onb::Onb;
 use super::pdf::Pdf;
+use super::pdf::{CosinePdf, MixturePdf, SpherePdf};
+use super::phase::{PhaseFunction, PhasePdf};
 use super::ray::Ray;
 use super::rtweekend;
+use super::spectrum;
 use super::vec3::{self, Vec3};
-use crate::texture::{SolidColor, Texture};
+use crate::texture::{self, SolidColor, Texture};
 use std::sync::Arc;
 
 pub trait Material: Send + Sync {
@@ -19,6 +25,83 @@ pub trait Material: Send + Sync {
     fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
         0.0
     }
+
+    // 沿 scattered 方向散射时的 BSDF 乘以余弦项。默认为 attenuation * scattering_pdf，
+    // 只有颜色随方向变化的材质（如带菲涅尔项的微表面）才需要重写。
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        srec.attenuation * self.scattering_pdf(r_in, rec, scattered)
+    }
+
+    // 表面在 (u, v, p) 处的不透明度。小于 1 时图元按 alpha_test 随机忽略这次命中。
+    fn alpha(&self, _u: f64, _v: f64, _p: vec3::Point3) -> f64 {
+        1.0
+    }
+
+    // emitted 是否可能不为零。发光的图元会被自动加入光源列表。
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // 单位面积向外发射的功率（按亮度计），即出射辐亮度在半球上的余弦积分。
+    fn emitted_power(&self) -> f64 {
+        0.0
+    }
+
+    // 设置了优先级或会吸收光的封闭物体的内部，积分器用它维护光线的内部栈。wavelength 为穿过表面的光线的波长。
+    fn nested_medium(&self, _wavelength: f64) -> Option<NestedMedium> {
+        None
+    }
+}
+
+pub fn alpha_test(alpha: f64) -> bool {
+    // 返回命中是否保留：完全不透明总是保留，部分透明以 alpha 的概率保留。
+    alpha >= 1.0 || (alpha > 0.0 && rtweekend::random_double() < alpha)
+}
+
+impl Material for Arc<dyn Material> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.as_ref().scatter(r_in, rec, srec)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: vec3::Point3) -> Color {
+        self.as_ref().emitted(r_in, rec, u, v, p)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.as_ref().scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.as_ref().bsdf_cos(r_in, rec, srec, scattered)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: vec3::Point3) -> f64 {
+        self.as_ref().alpha(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.as_ref().is_emissive()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        self.as_ref().emitted_power()
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        self.as_ref().nested_medium(wavelength)
+    }
 }
 
 #[derive(Clone)]
@@ -41,8 +124,11 @@ impl Lambertian<SolidColor> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
-        srec.attenuation = self.albedo.value(rec.u, rec.v, rec.p);
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.attenuation = spectrum::from_rgb(
+            self.albedo.value(rec.u, rec.v, rec.p),
+            r_in.hero_wavelength(),
+        );
         srec.pdf = Arc::new(CosinePdf::new(rec.normal));
         srec.skip_pdf = false;
         true
@@ -75,7 +161,7 @@ impl Metal {
 
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
-        srec.attenuation = self.albedo;
+        srec.attenuation = spectrum::from_rgb(self.albedo, r_in.hero_wavelength());
         srec.skip_pdf = true;
         let reflected = vec3::reflect(vec3::unit_vector(r_in.direction()), rec.normal);
         srec.skip_pdf_ray = Ray::new_with_time(
@@ -87,15 +173,387 @@ impl Material for Metal {
     }
 }
 
+#[derive(Clone)]
+pub enum Fresnel {
+    // F0 由 base color 和 metallic 插值得到（非金属取 0.04）。
+    Schlick,
+    // 导体的复折射率 eta + i k，按 RGB 通道给出。
+    Conductor { eta: Color, k: Color },
+}
+
+// 基于 GGX 微表面的金属度-粗糙度材质：漫反射层加上 Smith 遮蔽的镜面反射层。
+#[derive(Clone)]
+pub struct MetallicRoughness<T: Texture> {
+    pub base_color: T,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub anisotropy: f64,
+    pub fresnel: Fresnel,
+}
+
+struct MicrofacetLobes {
+    uvw: Onb,
+    wo: Vec3,
+    distribution: TrowbridgeReitz,
+    base_color: Color,
+    specular_probability: f64,
+}
+
+impl<T: Texture> MetallicRoughness<T> {
+    pub fn new_with_texture(base_color: T, metallic: f64, roughness: f64) -> Self {
+        Self {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            anisotropy: 0.0,
+            fresnel: Fresnel::Schlick,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Self {
+        // 高光沿表面切线（dp/du）方向拉长。
+        self.anisotropy = anisotropy.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_conductor(mut self, eta: Color, k: Color) -> Self {
+        // 使用导体菲涅尔项，材质视为纯金属，base color 作为额外的色调。
+        self.fresnel = Fresnel::Conductor { eta, k };
+        self.metallic = 1.0;
+        self
+    }
+
+    // base_color 已经上采样到光线的波长上，导体的复折射率在这里按主波长 hero 上采样。
+    fn fresnel(&self, base_color: Color, cos_theta: f64, hero: f64) -> Color {
+        match &self.fresnel {
+            Fresnel::Schlick => {
+                let f0 = Color::new(0.04, 0.04, 0.04) * (1.0 - self.metallic)
+                    + base_color * self.metallic;
+                microfacet::fresnel_schlick(f0, cos_theta)
+            }
+            Fresnel::Conductor { eta, k } => {
+                base_color
+                    * microfacet::fresnel_conductor(
+                        cos_theta,
+                        spectrum::from_rgb(*eta, hero),
+                        spectrum::from_rgb(*k, hero),
+                    )
+            }
+        }
+    }
+
+    fn lobes(&self, r_in: &Ray, rec: &HitRecord) -> MicrofacetLobes {
+        let uvw = Onb::new_from_w_u(rec.normal, rec.dpdu);
+        let mut wo = uvw.to_local(-vec3::unit_vector(r_in.direction()));
+        // 插值法线可能让视线落到着色半球之外，这里把它压回到半球边缘附近。
+        if wo.z() < 1e-4 {
+            wo[2] = 1e-4;
+            wo = vec3::unit_vector(wo);
+        }
+        let distribution = TrowbridgeReitz::from_roughness(self.roughness, self.anisotropy);
+        let base_color = spectrum::from_rgb(
+            self.base_color.value(rec.u, rec.v, rec.p),
+            r_in.hero_wavelength(),
+        );
+
+        // 按两层各自的大致反照率选择采样的层。
+        let f = self.fresnel(base_color, wo.z(), r_in.hero_wavelength());
+        let specular_weight = (f.x() + f.y() + f.z()) / 3.0;
+        let diffuse_weight =
+            (1.0 - self.metallic) * (base_color.x() + base_color.y() + base_color.z()) / 3.0
+                * (1.0 - specular_weight);
+        let specular_probability = if specular_weight + diffuse_weight > 0.0 {
+            specular_weight / (specular_weight + diffuse_weight)
+        } else {
+            1.0
+        };
+
+        MicrofacetLobes {
+            uvw,
+            wo,
+            distribution,
+            base_color,
+            specular_probability,
+        }
+    }
+}
+
+impl MetallicRoughness<SolidColor> {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        Self::new_with_texture(SolidColor::new(base_color), metallic, roughness)
+    }
+}
+
+impl<T: Texture> Material for MetallicRoughness<T> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let lobes = self.lobes(r_in, rec);
+        srec.attenuation = lobes.base_color;
+
+        // 完全光滑的金属退化为理想镜面反射，不参与光源采样。
+        if self.metallic >= 1.0 && lobes.distribution.effectively_smooth() {
+            let reflected = vec3::reflect(vec3::unit_vector(r_in.direction()), rec.normal);
+            srec.attenuation = self.fresnel(lobes.base_color, lobes.wo.z(), r_in.hero_wavelength());
+            srec.skip_pdf = true;
+            srec.skip_pdf_ray = Ray::new_with_time(rec.p, reflected, r_in.time());
+            return true;
+        }
+
+        let specular = Arc::new(MicrofacetReflectionPdf::new(
+            lobes.uvw,
+            lobes.wo,
+            lobes.distribution,
+        ));
+        srec.pdf = Arc::new(MixturePdf::new_with_weight(
+            specular,
+            Arc::new(CosinePdf::new(rec.normal)),
+            lobes.specular_probability,
+        ));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let lobes = self.lobes(r_in, rec);
+        let specular = MicrofacetReflectionPdf::new(lobes.uvw, lobes.wo, lobes.distribution);
+        let diffuse = CosinePdf::new(rec.normal);
+        lobes.specular_probability * specular.value(scattered.direction())
+            + (1.0 - lobes.specular_probability) * diffuse.value(scattered.direction())
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let lobes = self.lobes(r_in, rec);
+        let wo = lobes.wo;
+        let wi = lobes.uvw.to_local(vec3::unit_vector(scattered.direction()));
+        if wi.z() <= 0.0 {
+            return Color::default();
+        }
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return Color::default();
+        }
+        let wm = vec3::unit_vector(wm);
+
+        let f = self.fresnel(lobes.base_color, vec3::dot(wo, wm), r_in.hero_wavelength());
+        let d = lobes.distribution.d(wm);
+        let g = lobes.distribution.g(wo, wi);
+        let specular = f * (d * g / (4.0 * wo.z()));
+
+        let diffuse = (Color::one() - f)
+            * lobes.base_color
+            * ((1.0 - self.metallic) * wi.z() / rtweekend::PI);
+
+        specular + diffuse
+    }
+}
+
+// 折射率随波长变化的模型，波长以纳米为单位，公式中换算为微米。
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b_i λ² / (λ² - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // BK7 冕牌玻璃的 Sellmeier 系数。
+    pub fn bk7() -> Self {
+        Self::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    pub fn ior(&self, wavelength: f64) -> f64 {
+        let lambda = wavelength / 1000.0;
+        let lambda2 = lambda * lambda;
+        match self {
+            Self::Cauchy { a, b } => a + b / lambda2,
+            Self::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+}
+
+// RGB 三个通道的代表波长（纳米），每个通道覆盖两侧各 50 纳米的波段。
+const RGB_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+// 为还没有波长的光线选择一个波长：先等概率选择 RGB 中的一个通道，再在该通道的波段内均匀采样。
+// 返回的权重只保留该通道并乘以 3，使三个通道的期望仍为白色。
+// 谱渲染时直接取相机采样的主波长，权重为 1，由相机舍弃另外两个波长。
+pub fn sample_wavelength(r_in: &Ray) -> (f64, Color) {
+    if r_in.hero_wavelength() > 0.0 {
+        return (r_in.hero_wavelength(), Color::one());
+    }
+    let channel = rtweekend::random_int(0, 2) as usize;
+    let center = RGB_WAVELENGTHS[channel];
+    let mut weight = Color::default();
+    weight[channel] = 3.0;
+    (
+        rtweekend::random_double_range(center - 50.0, center + 50.0),
+        weight,
+    )
+}
+
+// 波长所在的 RGB 通道，光线还没有波长时返回 None。
+pub fn rgb_channel(wavelength: f64) -> Option<usize> {
+    if wavelength <= 0.0 {
+        None
+    } else if wavelength >= 600.0 {
+        Some(0)
+    } else if wavelength >= 500.0 {
+        Some(1)
+    } else {
+        Some(2)
+    }
+}
+
+// 表面上的薄膜涂层（肥皂泡、镀膜镜头），厚度以纳米为单位。厚度和折射率都可以由纹理控制。
+#[derive(Clone)]
+pub struct ThinFilm {
+    pub thickness: Arc<dyn Texture>,
+    pub ior: Arc<dyn Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        Self {
+            thickness: texture::scalar(thickness),
+            ior: texture::scalar(ior),
+        }
+    }
+
+    pub fn new_with_texture<T: Texture + 'static, U: Texture + 'static>(
+        thickness: T,
+        ior: U,
+    ) -> Self {
+        Self {
+            thickness: Arc::new(thickness),
+            ior: Arc::new(ior),
+        }
+    }
+
+    // 从折射率为 n0 的一侧射向基底 n2 时的反射率。光线带有波长时只计算该波长，
+    // 谱渲染时计算主波长为 hero 的几个波长，否则按 RGB 分别计算。
+    fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        n0: f64,
+        n2: f64,
+        wavelength: f64,
+        hero: f64,
+    ) -> Color {
+        let thickness = texture::scalar_value(&self.thickness, rec.u, rec.v, rec.p);
+        let n1 = texture::scalar_value(&self.ior, rec.u, rec.v, rec.p);
+        let r = |wavelength| {
+            microfacet::fresnel_thin_film(cos_theta, n0, n1, n2, thickness, wavelength)
+        };
+        let wavelengths = if hero > 0.0 {
+            spectrum::wavelengths(hero)
+        } else {
+            RGB_WAVELENGTHS
+        };
+        if wavelength > 0.0 {
+            let r = r(wavelength);
+            Color::new(r, r, r)
+        } else {
+            Color::new(r(wavelengths[0]), r(wavelengths[1]), r(wavelengths[2]))
+        }
+    }
+}
+
+// 按反射率选择反射还是透射，并把各通道之间的差异放进权重。返回是否反射以及权重。
+fn choose_reflection(reflectance: Color) -> (bool, Color) {
+    let p = (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0;
+    if p >= 1.0 {
+        (true, Color::one())
+    } else if p <= 0.0 {
+        (false, Color::one())
+    } else if rtweekend::random_double() < p {
+        (true, reflectance / p)
+    } else {
+        (false, (Color::one() - reflectance) / (1.0 - p))
+    }
+}
+
 #[derive(Clone)]
 pub struct Dielectric {
     pub ir: f64,
+    // 内部介质每单位长度的吸收系数（Beer–Lambert），为 0 时不吸收。
+    pub absorption: Color,
+    pub dispersion: Option<Dispersion>,
+    // 外表面上的薄膜涂层。
+    pub film: Option<ThinFilm>,
+    // 嵌套时的优先级，None 表示外侧总是空气。
+    priority: Option<u32>,
+    // 在内部栈中识别这个物体，用来计算光线在其中走过的距离上的吸收。克隆得到的材质编号相同，
+    // 属于同一个封闭物体（例如玻璃壳的内外两个球面、盒子的各个面）；互相重叠的不同物体
+    // 必须各自调用 Dielectric::new。
+    id: u32,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Self {
         Self {
             ir: index_of_refraction,
+            absorption: Color::default(),
+            dispersion: None,
+            film: None,
+            priority: None,
+            id: interior::next_id(),
+        }
+    }
+
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.absorption = absorption;
+        self
+    }
+
+    // 透过距离 distance 后剩余 transmittance 的颜色，常用于描述有色玻璃和液体。
+    pub fn with_transmittance(self, transmittance: Color, distance: f64) -> Self {
+        assert!(distance > 0.0, "transmittance distance must be positive");
+        let absorption = |t: f64| -t.max(1e-6).ln() / distance;
+        self.with_absorption(Color::new(
+            absorption(transmittance.x()),
+            absorption(transmittance.y()),
+            absorption(transmittance.z()),
+        ))
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Self {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    // 参与嵌套：外侧的折射率取光线所在的物体，与优先级更高的物体重叠的部分被忽略。
+    // 杯中的液体可以做得比杯子的内壁稍大，让玻璃的优先级更高即可。杯子和液体各自
+    // 由 Dielectric::new 创建，不能克隆同一个材质。
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    // 外侧的折射率。没有设置优先级时外侧总是空气。
+    fn outside_ior(&self, rec: &HitRecord) -> f64 {
+        match self.priority {
+            Some(_) => rec.outside_ior.unwrap_or(1.0),
+            None => 1.0,
         }
     }
 
@@ -108,12 +566,27 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        // 内部的吸收由积分器按内部栈在光线走过的每一段上计算。
         srec.attenuation = Color::new(1.0, 1.0, 1.0);
         srec.skip_pdf = true;
+
+        let mut wavelength = r_in.wavelength();
+        let ir = match &self.dispersion {
+            Some(dispersion) => {
+                if wavelength == 0.0 {
+                    let (sampled, weight) = sample_wavelength(r_in);
+                    wavelength = sampled;
+                    srec.attenuation = srec.attenuation * weight;
+                }
+                dispersion.ior(wavelength)
+            }
+            None => self.ir,
+        };
+        let outside_ior = self.outside_ior(rec);
         let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
+            outside_ior / ir
         } else {
-            self.ir
+            ir / outside_ior
         };
 
         let unit_direction = vec3::unit_vector(r_in.direction());
@@ -122,35 +595,296 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract
-            || Self::reflectance(cos_theta, refraction_ratio) > rtweekend::random_double()
-        {
+        let reflect = if cannot_refract {
+            true
+        } else if let Some(film) = &self.film {
+            let (n0, n2) = if rec.front_face {
+                (outside_ior, ir)
+            } else {
+                (ir, outside_ior)
+            };
+            let (reflect, weight) = choose_reflection(film.reflectance(
+                rec,
+                cos_theta,
+                n0,
+                n2,
+                wavelength,
+                r_in.hero_wavelength(),
+            ));
+            srec.attenuation = srec.attenuation * weight;
+            reflect
+        } else {
+            Self::reflectance(cos_theta, refraction_ratio) > rtweekend::random_double()
+        };
+        let direction = if reflect {
             vec3::reflect(unit_direction, rec.normal)
         } else {
             vec3::refract(unit_direction, rec.normal, refraction_ratio)
         };
 
-        srec.skip_pdf_ray = Ray::new_with_time(rec.p, direction, r_in.time());
+        srec.skip_pdf_ray =
+            Ray::new_with_time(rec.p, direction, r_in.time()).with_wavelength(wavelength);
+        true
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        if self.priority.is_none() && self.absorption.near_zero() {
+            return None;
+        }
+        let ior = match (&self.dispersion, wavelength > 0.0) {
+            (Some(dispersion), true) => dispersion.ior(wavelength),
+            _ => self.ir,
+        };
+        Some(NestedMedium {
+            id: self.id,
+            priority: self.priority.unwrap_or(0),
+            ior,
+            absorption: self.absorption,
+            prioritized: self.priority.is_some(),
+        })
+    }
+}
+
+// 没有厚度的薄壁电介质（玻璃板、肥皂泡），把两个平行界面之间的多次反射合在一起计算，
+// 透射光线保持原方向穿过表面。可选的薄膜涂在外侧（几何法线朝向的一侧）的表面上，基底折射率为 1 时就是肥皂泡。
+#[derive(Clone)]
+pub struct ThinDielectric {
+    pub ir: f64,
+    pub film: Option<ThinFilm>,
+}
+
+impl ThinDielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            ir: index_of_refraction,
+            film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+}
+
+impl Material for ThinDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = true;
+        let unit_direction = vec3::unit_vector(r_in.direction());
+        let cos_theta = vec3::dot(-unit_direction, rec.normal).clamp(0.0, 1.0);
+
+        // 薄膜只涂在外侧的界面上，另一个界面是普通的基底与空气界面。
+        // 无损的薄膜从两侧入射的反射率相同，从背面入射时只是两个界面的先后顺序相反。
+        let r_plain = microfacet::fresnel_dielectric(cos_theta, self.ir);
+        let r_film = match &self.film {
+            Some(film) => film.reflectance(
+                rec,
+                cos_theta,
+                1.0,
+                self.ir,
+                r_in.wavelength(),
+                r_in.hero_wavelength(),
+            ),
+            None => Color::new(r_plain, r_plain, r_plain),
+        };
+        let (r1, r2) = if rec.front_face {
+            (r_film, Color::new(r_plain, r_plain, r_plain))
+        } else {
+            (Color::new(r_plain, r_plain, r_plain), r_film)
+        };
+
+        // 板内的多次反射是非相干叠加的：R = R1 + T1² R2 / (1 - R1 R2)。
+        let mut reflectance = Color::default();
+        for c in 0..3 {
+            let t1 = 1.0 - r1[c];
+            let denom = 1.0 - r1[c] * r2[c];
+            reflectance[c] = if denom > 0.0 {
+                r1[c] + t1 * t1 * r2[c] / denom
+            } else {
+                1.0
+            };
+        }
+
+        let (reflect, weight) = choose_reflection(reflectance);
+        srec.attenuation = weight;
+        let direction = if reflect {
+            vec3::reflect(unit_direction, rec.normal)
+        } else {
+            unit_direction
+        };
+        srec.skip_pdf_ray =
+            Ray::new_with_time(rec.p, direction, r_in.time()).with_wavelength(r_in.wavelength());
         true
     }
 }
 
+// 粗糙的电介质（磨砂玻璃），使用 Walter et al. 的 GGX 微表面反射与透射。
+// 粗糙度来自纹理的通道平均值，趋于 0 时退化为光滑的 Dielectric。
+#[derive(Clone)]
+pub struct RoughDielectric<T: Texture> {
+    pub ir: f64,
+    pub roughness: T,
+    pub anisotropy: f64,
+    // 粗糙度趋于 0 时使用的光滑电介质，同时给出嵌套时的优先级、内部的吸收和编号。
+    // 与 Dielectric 相同，不同的物体各自创建材质。
+    smooth: Dielectric,
+}
+
+impl<T: Texture> RoughDielectric<T> {
+    pub fn new_with_texture(index_of_refraction: f64, roughness: T) -> Self {
+        Self {
+            ir: index_of_refraction,
+            roughness,
+            anisotropy: 0.0,
+            smooth: Dielectric::new(index_of_refraction),
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Self {
+        self.anisotropy = anisotropy.clamp(0.0, 1.0);
+        self
+    }
+
+    // 与 Dielectric 的同名方法相同。
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.smooth = self.smooth.with_absorption(absorption);
+        self
+    }
+
+    pub fn with_transmittance(mut self, transmittance: Color, distance: f64) -> Self {
+        self.smooth = self.smooth.with_transmittance(transmittance, distance);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.smooth = self.smooth.with_priority(priority);
+        self
+    }
+
+    fn lobe(&self, r_in: &Ray, rec: &HitRecord) -> (Onb, Vec3, MicrofacetDielectric) {
+        let uvw = Onb::new_from_w_u(rec.normal, rec.dpdu);
+        let mut wo = uvw.to_local(-vec3::unit_vector(r_in.direction()));
+        if wo.z() < 1e-4 {
+            wo[2] = 1e-4;
+            wo = vec3::unit_vector(wo);
+        }
+        let roughness = texture::scalar_value(&self.roughness, rec.u, rec.v, rec.p);
+        let distribution =
+            TrowbridgeReitz::from_roughness(roughness.clamp(0.0, 1.0), self.anisotropy);
+        // 法线总是朝向入射一侧，eta 为另一侧与入射侧的折射率之比。
+        let outside_ior = self.smooth.outside_ior(rec);
+        let eta = if rec.front_face {
+            self.ir / outside_ior
+        } else {
+            outside_ior / self.ir
+        };
+        (uvw, wo, MicrofacetDielectric::new(distribution, eta))
+    }
+}
+
+impl RoughDielectric<SolidColor> {
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self {
+        Self::new_with_texture(
+            index_of_refraction,
+            SolidColor::new_with_rgb(roughness, roughness, roughness),
+        )
+    }
+}
+
+impl<T: Texture> Material for RoughDielectric<T> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let (uvw, wo, dielectric) = self.lobe(r_in, rec);
+        if dielectric.distribution.effectively_smooth() {
+            return self.smooth.scatter(r_in, rec, srec);
+        }
+
+        srec.attenuation = Color::one();
+        srec.pdf = Arc::new(MicrofacetDielectricPdf::new(uvw, wo, dielectric));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let (uvw, wo, dielectric) = self.lobe(r_in, rec);
+        dielectric.pdf(wo, uvw.to_local(vec3::unit_vector(scattered.direction())))
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let (uvw, wo, dielectric) = self.lobe(r_in, rec);
+        let f = dielectric.bsdf_cos(wo, uvw.to_local(vec3::unit_vector(scattered.direction())));
+        Color::new(f, f, f)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: vec3::Point3) -> f64 {
+        self.smooth.alpha(u, v, p)
+    }
+
+    fn nested_medium(&self, wavelength: f64) -> Option<NestedMedium> {
+        self.smooth.nested_medium(wavelength)
+    }
+}
+
+// 光源的发射方向分布。
+#[derive(Debug, Clone, Copy)]
+pub enum EmissionProfile {
+    // 各个方向的辐亮度相同。
+    Diffuse,
+    // 聚光灯：辐亮度按 cos^exponent 随偏离法线的角度衰减。
+    Spot { exponent: f64 },
+}
+
 #[derive(Clone)]
 pub struct DiffuseLight<T: Texture> {
     pub emit: T,
+    // 乘在发射纹理上的强度和颜色（例如色温）。
+    pub scale: Color,
+    pub two_sided: bool,
+    pub profile: EmissionProfile,
 }
 
 impl<T: Texture> DiffuseLight<T> {
     pub fn new(a: T) -> Self {
-        Self { emit: a }
+        Self {
+            emit: a,
+            scale: Color::one(),
+            two_sided: false,
+            profile: EmissionProfile::Diffuse,
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.scale *= strength;
+        self
+    }
+
+    // 按黑体辐射的颜色着色，亮度不变。
+    pub fn with_temperature(mut self, kelvin: f64) -> Self {
+        self.scale = self.scale * color::blackbody(kelvin);
+        self
+    }
+
+    pub fn two_sided(mut self) -> Self {
+        self.two_sided = true;
+        self
+    }
+
+    pub fn with_spot(mut self, exponent: f64) -> Self {
+        self.profile = EmissionProfile::Spot {
+            exponent: exponent.max(0.0),
+        };
+        self
     }
 }
 
 impl DiffuseLight<SolidColor> {
     pub fn new_with_color(c: Color) -> Self {
-        Self {
-            emit: SolidColor::new(c),
-        }
+        Self::new(SolidColor::new(c))
     }
 }
 
@@ -159,12 +893,35 @@ impl<T: Texture> Material for DiffuseLight<T> {
         false
     }
 
-    fn emitted(&self, _r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: vec3::Point3) -> Color {
-        if rec.front_face {
-            self.emit.value(u, v, p)
-        } else {
-            Color::default()
+    fn is_emissive(&self) -> bool {
+        !self.scale.near_zero() && !self.emit.is_black()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        // cos^n 衰减的聚光灯在半球上的余弦积分为 2π / (n + 2)，漫射光为 π。
+        let falloff = match self.profile {
+            EmissionProfile::Diffuse => 1.0,
+            EmissionProfile::Spot { exponent } => 2.0 / (exponent + 2.0),
+        };
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        rtweekend::PI * sides * falloff * color::luminance(self.emit.average() * self.scale)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: vec3::Point3) -> Color {
+        if !rec.front_face && !self.two_sided {
+            return Color::default();
         }
+        let falloff = match self.profile {
+            EmissionProfile::Diffuse => 1.0,
+            EmissionProfile::Spot { exponent } => {
+                let cos_theta = vec3::dot(-vec3::unit_vector(r_in.direction()), rec.normal);
+                cos_theta.max(0.0).powf(exponent)
+            }
+        };
+        spectrum::from_rgb(
+            self.emit.value(u, v, p) * self.scale,
+            r_in.hero_wavelength(),
+        ) * falloff
     }
 }
 
@@ -188,10 +945,14 @@ impl Isotropic<SolidColor> {
 }
 
 impl<T: Texture> Material for Isotropic<T> {
-    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
-        srec.attenuation = self.albedo.value(rec.u, rec.v, rec.p);
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.attenuation = spectrum::from_rgb(
+            self.albedo.value(rec.u, rec.v, rec.p),
+            r_in.hero_wavelength(),
+        );
         srec.pdf = Arc::new(SpherePdf {});
         srec.skip_pdf = false;
+        srec.volume = true;
         true
     }
 
@@ -200,6 +961,49 @@ impl<T: Texture> Material for Isotropic<T> {
     }
 }
 
+// 按相函数 phase 散射的介质，例如前向散射的雾和云。
+#[derive(Clone)]
+pub struct Anisotropic<T: Texture, P: PhaseFunction + Clone> {
+    pub albedo: T,
+    pub phase: P,
+}
+
+impl<T: Texture, P: PhaseFunction + Clone> Anisotropic<T, P> {
+    pub fn new(a: T, phase: P) -> Self {
+        Self { albedo: a, phase }
+    }
+}
+
+impl<P: PhaseFunction + Clone> Anisotropic<SolidColor, P> {
+    pub fn new_with_color(c: Color, phase: P) -> Self {
+        Self {
+            albedo: SolidColor::new(c),
+            phase,
+        }
+    }
+}
+
+impl<T: Texture, P: PhaseFunction + Clone + 'static> Material for Anisotropic<T, P> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.attenuation = spectrum::from_rgb(
+            self.albedo.value(rec.u, rec.v, rec.p),
+            r_in.hero_wavelength(),
+        );
+        srec.pdf = Arc::new(PhasePdf::new(r_in.direction(), self.phase.clone()));
+        srec.skip_pdf = false;
+        srec.volume = true;
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = vec3::dot(
+            vec3::unit_vector(r_in.direction()),
+            vec3::unit_vector(scattered.direction()),
+        );
+        self.phase.p(cos_theta)
+    }
+}
+
 #[derive(Clone)]
 pub struct NonePdf;
 
@@ -219,6 +1023,8 @@ pub struct ScatterRecord {
     pub pdf: Arc<dyn Pdf>,
     pub skip_pdf: bool,
     pub skip_pdf_ray: Ray,
+    // 介质内部的散射。相机按反弹的类型分别限制次数。
+    pub volume: bool,
 }
 
 impl Default for ScatterRecord {
@@ -228,6 +1034,162 @@ impl Default for ScatterRecord {
             pdf: Arc::new(NonePdf {}),
             skip_pdf: false,
             skip_pdf_ray: Ray::default(),
+            volume: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IOR: f64 = 1.5;
+    const SAMPLES: usize = 20000;
+
+    // 以 cos_theta 入射到 z = 0 平面的光线，着色法线总是 +z。front_face 为 false 时光线从玻璃内部射出。
+    fn hit(cos_theta: f64, front_face: bool) -> (Ray, HitRecord) {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let r = Ray::new(
+            Vec3::new(-sin_theta, 0.0, cos_theta),
+            Vec3::new(sin_theta, 0.0, -cos_theta),
+        );
+        let outward = if front_face {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(0.0, 0.0, -1.0)
+        };
+        let mut rec = HitRecord {
+            t: 1.0,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        rec.set_face_normal(&r, outward);
+        (r, rec)
+    }
+
+    // 散射的方向和权重。按 PDF 采样的材质取 bsdf_cos / pdf 作为权重。
+    fn sample(mat: &dyn Material, r: &Ray, rec: &HitRecord) -> (Vec3, f64) {
+        let mut srec = ScatterRecord::default();
+        assert!(mat.scatter(r, rec, &mut srec));
+        if srec.skip_pdf {
+            return (
+                vec3::unit_vector(srec.skip_pdf_ray.direction()),
+                srec.attenuation.x(),
+            );
+        }
+        let direction = vec3::unit_vector(srec.pdf.generate());
+        let scattered = Ray::new(rec.p, direction);
+        let pdf = srec.pdf.value(direction);
+        let f = mat.bsdf_cos(r, rec, &srec, &scattered).x();
+        assert!(pdf.is_finite() && f.is_finite() && f >= 0.0);
+        // 个别微表面采样得到的方向落在不可能的一侧，PDF 为 0，积分器把它当作零贡献。
+        (direction, if pdf > 0.0 { f / pdf } else { 0.0 })
+    }
+
+    // 反射所占的比例，并检查方向平均接近光滑表面的反射或折射方向，平均权重接近 1。
+    // 微表面的分布有长尾，个别样本可能偏离几度，所以只检查平均值。
+    fn reflect_fraction(mat: &dyn Material, cos_theta: f64, front_face: bool) -> f64 {
+        let (r, rec) = hit(cos_theta, front_face);
+        let unit_direction = vec3::unit_vector(r.direction());
+        let ratio = if front_face { 1.0 / IOR } else { IOR };
+        let reflected = vec3::reflect(unit_direction, rec.normal);
+        let refracted = vec3::refract(unit_direction, rec.normal, ratio);
+
+        let mut reflections = 0;
+        let mut deviation = 0.0;
+        let mut weight = 0.0;
+        for _ in 0..SAMPLES {
+            let (direction, w) = sample(mat, &r, &rec);
+            let expected = if vec3::dot(direction, rec.normal) > 0.0 {
+                reflections += 1;
+                reflected
+            } else {
+                refracted
+            };
+            deviation += 1.0 - vec3::dot(direction, expected);
+            weight += w;
+        }
+        let deviation = deviation / SAMPLES as f64;
+        let weight = weight / SAMPLES as f64;
+        assert!(deviation < 1e-3, "cos {cos_theta}: deviation {deviation}");
+        assert!(
+            (weight - 1.0).abs() < 0.02,
+            "cos {cos_theta}: weight {weight}"
+        );
+        reflections as f64 / SAMPLES as f64
+    }
+
+    #[test]
+    fn rough_dielectric_matches_smooth_at_low_roughness() {
+        // 粗糙度 1e-3 时 alpha 低于阈值，直接按光滑表面处理；0.035 时仍走微表面的采样。
+        for roughness in [1e-3, 0.035] {
+            for (cos_theta, front_face) in [(0.9, true), (0.3, true), (0.9, false), (0.6, false)] {
+                let smooth = reflect_fraction(&Dielectric::new(IOR), cos_theta, front_face);
+                let rough =
+                    reflect_fraction(&RoughDielectric::new(IOR, roughness), cos_theta, front_face);
+                assert!(
+                    (smooth - rough).abs() < 0.02,
+                    "roughness {roughness}, cos {cos_theta}: {smooth} vs {rough}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn microfacet_dielectric_converges_to_smooth() {
+        let cos_theta: f64 = 0.8;
+        let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+        for eta in [IOR, 1.0 / IOR] {
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            let reflected = vec3::reflect(-wo, normal);
+            let refracted = microfacet::refract(wo, normal, eta).unwrap();
+            let mut last_deviation = f64::INFINITY;
+            let mut last_pdf = [0.0; 2];
+
+            for alpha in [1e-1, 1e-2, 1e-3] {
+                let lobe = MicrofacetDielectric::new(TrowbridgeReitz::new(alpha, alpha), eta);
+
+                // 在理想的反射和折射方向上，PDF 随 alpha 减小而增大，BSDF 与 PDF 之比保持有限。
+                for (k, wi) in [reflected, refracted].into_iter().enumerate() {
+                    let pdf = lobe.pdf(wo, wi);
+                    let f = lobe.bsdf_cos(wo, wi);
+                    assert!(pdf.is_finite() && f.is_finite() && pdf > last_pdf[k]);
+                    assert!((f / pdf - 1.0).abs() < 0.05, "eta {eta}, alpha {alpha}");
+                    last_pdf[k] = pdf;
+                }
+
+                let mut weight = 0.0;
+                let mut deviation = 0.0;
+                let mut reflections = 0;
+                for _ in 0..SAMPLES {
+                    let wi = lobe.sample(wo);
+                    let pdf = lobe.pdf(wo, wi);
+                    let f = lobe.bsdf_cos(wo, wi);
+                    assert!(pdf.is_finite() && f.is_finite() && f >= 0.0);
+                    if pdf > 0.0 {
+                        weight += f / pdf;
+                    }
+                    let expected = if wi.z() > 0.0 {
+                        reflections += 1;
+                        reflected
+                    } else {
+                        refracted
+                    };
+                    deviation += 1.0 - vec3::dot(wi, expected);
+                }
+                let weight = weight / SAMPLES as f64;
+                let deviation = deviation / SAMPLES as f64;
+                assert!(deviation < last_deviation);
+                last_deviation = deviation;
+
+                if alpha <= 1e-3 {
+                    let fresnel = microfacet::fresnel_dielectric(cos_theta, eta);
+                    let fraction = reflections as f64 / SAMPLES as f64;
+                    assert!((weight - 1.0).abs() < 0.01, "eta {eta}: weight {weight}");
+                    assert!((fraction - fresnel).abs() < 0.02, "{fraction} vs {fresnel}");
+                    assert!(deviation < 1e-4);
+                }
+            }
         }
     }
 }
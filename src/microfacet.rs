@@ -0,0 +1,352 @@
+use super::color::Color;
+use super::onb::Onb;
+use super::pdf::Pdf;
+use super::rtweekend;
+use super::vec3::{self, Vec3};
+
+// Trowbridge-Reitz (GGX) 微表面分布。所有方向都在局部坐标系中，z 轴为宏观法线，
+// x、y 轴分别对应 alpha_x、alpha_y 两个粗糙度方向。
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        // Disney 的参数化：alpha = roughness^2，各向异性沿切线方向拉长。
+        let alpha = roughness * roughness;
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self::new(alpha / aspect, alpha * aspect)
+    }
+
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let z = wm.z();
+        let denom = x * x + y * y + z * z;
+        if z <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (rtweekend::PI * self.alpha_x * self.alpha_y * denom * denom)
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let z2 = w.z() * w.z();
+        if z2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let x = self.alpha_x * w.x();
+        let y = self.alpha_y * w.y();
+        ((1.0 + (x * x + y * y) / z2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Smith 高度相关的遮蔽-阴影项。
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // 从 wo 方向可见的法线分布 D_wo(wm)。
+    pub fn d_visible(&self, wo: Vec3, wm: Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / wo.z() * self.d(wm) * vec3::dot(wo, wm).max(0.0)
+    }
+
+    pub fn sample_wm(&self, wo: Vec3) -> Vec3 {
+        // Heitz 2018：把视线拉伸到各向同性的单位粗糙度空间，在投影半球上采样后再变换回来。
+        let vh = vec3::unit_vector(Vec3::new(
+            self.alpha_x * wo.x(),
+            self.alpha_y * wo.y(),
+            wo.z(),
+        ));
+
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1_axis = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2_axis = vec3::cross(vh, t1_axis);
+
+        let r = rtweekend::random_double().sqrt();
+        let phi = 2.0 * rtweekend::PI * rtweekend::random_double();
+        let t1 = r * phi.cos();
+        let t2 = r * phi.sin();
+        let s = 0.5 * (1.0 + vh.z());
+        let t2 = (1.0 - s) * (1.0 - t1 * t1).sqrt() + s * t2;
+
+        let nh = t1 * t1_axis + t2 * t2_axis + (1.0 - t1 * t1 - t2 * t2).max(0.0).sqrt() * vh;
+        vec3::unit_vector(Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        ))
+    }
+}
+
+pub fn fresnel_schlick(f0: Color, cos_theta: f64) -> Color {
+    let m = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color::one() - f0) * m
+}
+
+// 非偏振光在电介质界面上的菲涅尔反射率。eta 为透射侧与入射侧折射率之比。
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(-1.0, 1.0);
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+// 入射侧 n0、薄膜 n1（厚度 thickness 纳米）、基底 n2 三层结构的反射率（Airy 公式），
+// 两个界面反射的光发生干涉，结果随波长（纳米）变化。s、p 两个偏振分别计算后取平均。
+pub fn fresnel_thin_film(
+    cos_theta_i: f64,
+    n0: f64,
+    n1: f64,
+    n2: f64,
+    thickness: f64,
+    wavelength: f64,
+) -> f64 {
+    let cos0 = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_0 = 1.0 - cos0 * cos0;
+    let sin2_2 = sin2_0 * (n0 / n2).powi(2);
+    if sin2_2 >= 1.0 {
+        return 1.0;
+    }
+    let sin2_1 = sin2_0 * (n0 / n1).powi(2);
+    if sin2_1 >= 1.0 || thickness <= 0.0 {
+        // 薄膜内全反射或者没有薄膜时，忽略干涉。
+        return fresnel_dielectric(cos0, n2 / n0);
+    }
+    let cos1 = (1.0 - sin2_1).sqrt();
+    let cos2 = (1.0 - sin2_2).sqrt();
+
+    let cos_delta = (4.0 * rtweekend::PI * n1 * thickness * cos1 / wavelength).cos();
+    let airy = |r01: f64, r12: f64| {
+        let cross = 2.0 * r01 * r12 * cos_delta;
+        (r01 * r01 + r12 * r12 + cross) / (1.0 + r01 * r01 * r12 * r12 + cross)
+    };
+    let rs = airy(
+        (n0 * cos0 - n1 * cos1) / (n0 * cos0 + n1 * cos1),
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+    );
+    let rp = airy(
+        (n1 * cos0 - n0 * cos1) / (n1 * cos0 + n0 * cos1),
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+    );
+    (0.5 * (rs + rp)).clamp(0.0, 1.0)
+}
+
+// 导体的菲涅尔反射率，eta + i k 为复折射率，按颜色通道分别计算。
+pub fn fresnel_conductor(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    let mut result = Color::default();
+    for c in 0..3 {
+        result[c] = fresnel_complex(cos_theta_i, eta[c], k[c]);
+    }
+    result
+}
+
+fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2plusb2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2plusb2 + cos2;
+    let a = (0.5 * (a2plusb2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2plusb2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+// 按可见法线分布采样反射方向的 PDF。
+pub struct MicrofacetReflectionPdf {
+    uvw: Onb,
+    wo: Vec3,
+    distribution: TrowbridgeReitz,
+}
+
+impl MicrofacetReflectionPdf {
+    pub fn new(uvw: Onb, wo: Vec3, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            uvw,
+            wo,
+            distribution,
+        }
+    }
+}
+
+impl Pdf for MicrofacetReflectionPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let wi = self.uvw.to_local(vec3::unit_vector(direction));
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm = self.wo + wi;
+        if wm.near_zero() {
+            return 0.0;
+        }
+        let wm = vec3::unit_vector(wm);
+        self.distribution.d_visible(self.wo, wm) / (4.0 * vec3::dot(self.wo, wm).abs())
+    }
+
+    fn generate(&self) -> Vec3 {
+        let wm = self.distribution.sample_wm(self.wo);
+        self.uvw.local_v(vec3::reflect(-self.wo, wm))
+    }
+}
+
+// 绕微表面法线 n 折射 wi（两者在同一侧），eta 为透射侧与入射侧折射率之比。全反射时返回 None。
+pub fn refract(wi: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = vec3::dot(n, wi);
+    let sin2_theta_i = (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    let sin2_theta_t = sin2_theta_i / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-wi / eta + (cos_theta_i / eta - cos_theta_t) * n)
+}
+
+// Walter et al. 2007 的粗糙电介质界面，同时包含反射和透射。
+// 约定 wo 在局部坐标系的上半球（z > 0），eta 为下半球一侧与 wo 一侧折射率之比。
+#[derive(Debug, Clone, Copy)]
+pub struct MicrofacetDielectric {
+    pub distribution: TrowbridgeReitz,
+    pub eta: f64,
+}
+
+impl MicrofacetDielectric {
+    pub fn new(distribution: TrowbridgeReitz, eta: f64) -> Self {
+        Self { distribution, eta }
+    }
+
+    // 广义半程向量：反射时为 wo + wi，透射时为 wo + eta * wi，统一朝向上半球。
+    // 背向的微表面不可能产生这对方向，返回 None。
+    fn half_vector(&self, wo: Vec3, wi: Vec3) -> Option<(Vec3, f64)> {
+        let cos_theta_o = wo.z();
+        let cos_theta_i = wi.z();
+        if cos_theta_o <= 0.0 || cos_theta_i == 0.0 {
+            return None;
+        }
+        let etap = if cos_theta_i > 0.0 { 1.0 } else { self.eta };
+        let wm = wi * etap + wo;
+        if wm.near_zero() {
+            return None;
+        }
+        let wm = vec3::unit_vector(wm);
+        let wm = if wm.z() < 0.0 { -wm } else { wm };
+        if vec3::dot(wm, wi) * cos_theta_i < 0.0 || vec3::dot(wm, wo) * cos_theta_o < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    pub fn bsdf_cos(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let Some((wm, etap)) = self.half_vector(wo, wi) else {
+            return 0.0;
+        };
+        let f = fresnel_dielectric(vec3::dot(wo, wm), self.eta);
+        let d = self.distribution.d(wm);
+        let g = self.distribution.g(wo, wi);
+
+        if wi.z() > 0.0 {
+            d * g * f / (4.0 * wo.z())
+        } else {
+            // 与光滑的 Dielectric 一致，不按 1/eta^2 缩放辐亮度，单面的玻璃板也不会变暗。
+            let denom = vec3::dot(wi, wm) + vec3::dot(wo, wm) / etap;
+            let denom = denom * denom;
+            d * g * (1.0 - f) * (vec3::dot(wi, wm) * vec3::dot(wo, wm)).abs() / (wo.z() * denom)
+        }
+    }
+
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let Some((wm, etap)) = self.half_vector(wo, wi) else {
+            return 0.0;
+        };
+        let r = fresnel_dielectric(vec3::dot(wo, wm), self.eta);
+        let d_visible = self.distribution.d_visible(wo, wm);
+
+        if wi.z() > 0.0 {
+            r * d_visible / (4.0 * vec3::dot(wo, wm).abs())
+        } else {
+            // 折射方向对半程向量的雅可比 dwm/dwi。
+            let denom = vec3::dot(wi, wm) + vec3::dot(wo, wm) / etap;
+            let dwm_dwi = vec3::dot(wi, wm).abs() / (denom * denom);
+            (1.0 - r) * d_visible * dwm_dwi
+        }
+    }
+
+    pub fn sample(&self, wo: Vec3) -> Vec3 {
+        let wm = self.distribution.sample_wm(wo);
+        let r = fresnel_dielectric(vec3::dot(wo, wm), self.eta);
+        if rtweekend::random_double() < r {
+            return vec3::reflect(-wo, wm);
+        }
+        refract(wo, wm, self.eta).unwrap_or_else(|| vec3::reflect(-wo, wm))
+    }
+}
+
+pub struct MicrofacetDielectricPdf {
+    uvw: Onb,
+    wo: Vec3,
+    dielectric: MicrofacetDielectric,
+}
+
+impl MicrofacetDielectricPdf {
+    pub fn new(uvw: Onb, wo: Vec3, dielectric: MicrofacetDielectric) -> Self {
+        Self {
+            uvw,
+            wo,
+            dielectric,
+        }
+    }
+}
+
+impl Pdf for MicrofacetDielectricPdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let wi = self.uvw.to_local(vec3::unit_vector(direction));
+        self.dielectric.pdf(self.wo, wi)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.local_v(self.dielectric.sample(self.wo))
+    }
+}
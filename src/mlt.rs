@@ -0,0 +1,337 @@
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::camera::{Camera, Film};
+use super::color::{self, Color};
+use super::hittable::Hittable;
+use super::integrator::{Integrator, PathTracer};
+use super::light::AliasTable;
+use super::ray::Ray;
+use super::rtweekend;
+use super::sampler::{self, Sampler};
+
+// 主样本空间中的一维。backup 是变异之前的值，变异被拒绝时恢复。
+#[derive(Clone, Copy)]
+struct PrimarySample {
+    value: f64,
+    last_modified: u64,
+    backup: f64,
+    backup_modified: u64,
+}
+
+// 主样本空间 Metropolis 的采样器。路径追踪依次取用的随机数构成主样本空间中的一个点，
+// 每次迭代对这些数做大步变异（重新均匀采样）或小步变异（加上正态分布的扰动）。
+// 变异是惰性的：某一维只在被取用时才补上自它上次修改以来漏掉的变异。
+pub struct MltSampler {
+    rng: StdRng,
+    sigma: f64,
+    large_step_probability: f64,
+    x: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+}
+
+impl MltSampler {
+    // 第 0 次迭代是大步变异，同一个种子总是得到主样本空间中的同一个点。
+    pub fn new(seed: u64, sigma: f64, large_step_probability: f64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            sigma,
+            large_step_probability,
+            x: Vec::new(),
+            index: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+        }
+    }
+
+    // 之后的变异改用另一个种子，避免从同一个初始点出发的链完全相同。
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.r#gen::<f64>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    pub fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    pub fn reject(&mut self) {
+        for x in self.x.iter_mut() {
+            if x.last_modified == self.iteration {
+                x.value = x.backup;
+                x.last_modified = x.backup_modified;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    // 标准正态分布的样本（Box-Muller 变换）。
+    fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.rng.r#gen::<f64>();
+        let u2 = self.rng.r#gen::<f64>();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * rtweekend::PI * u2).cos()
+    }
+}
+
+impl Sampler for MltSampler {
+    fn next_1d(&mut self) -> f64 {
+        // 路径变长时新出现的维直接均匀采样。如果从 0 开始小步变异，拒绝采样（例如单位圆盘中取点）
+        // 每次都会落在角上，永远不能结束。
+        if self.index >= self.x.len() {
+            let value = self.rng.r#gen::<f64>();
+            self.x.push(PrimarySample {
+                value,
+                last_modified: self.iteration,
+                backup: value,
+                backup_modified: self.iteration,
+            });
+        }
+        let i = self.index;
+        self.index += 1;
+
+        // 上次大步变异之后没有被取用过的维，它的值在那次大步变异中已经重新采样过。
+        if self.x[i].last_modified < self.last_large_step {
+            self.x[i].value = self.rng.r#gen::<f64>();
+            self.x[i].last_modified = self.last_large_step;
+        }
+
+        let x = self.x[i];
+        self.x[i].backup = x.value;
+        self.x[i].backup_modified = x.last_modified;
+        self.x[i].value = if self.large_step {
+            self.rng.r#gen::<f64>()
+        } else {
+            // 错过的 n 次小步变异合起来相当于标准差放大 sqrt(n) 倍的一次变异。
+            let n = (self.iteration - x.last_modified) as f64;
+            let value = x.value + self.normal() * self.sigma * n.sqrt();
+            value - value.floor()
+        };
+        self.x[i].last_modified = self.iteration;
+        self.x[i].value
+    }
+}
+
+// 一条路径的贡献：图像上的位置和它带来的辐亮度。
+#[derive(Clone, Copy, Default)]
+struct PathSample {
+    x: f64,
+    y: f64,
+    radiance: Color,
+}
+
+impl PathSample {
+    // 马尔可夫链的目标分布正比于这个值。
+    fn importance(&self) -> f64 {
+        color::luminance(self.radiance).max(0.0)
+    }
+}
+
+// 主样本空间 Metropolis 光传输（PSSMLT）。先用 bootstrap_samples 条独立的路径估计整幅图像的
+// 平均亮度 b，再按路径贡献的亮度成比例地选出 chains 条马尔可夫链的起点，每条链不断变异路径追踪
+// 所用的随机数，相邻的路径往往仍能穿过钥匙孔或玻璃找到光源。每个状态把贡献除以亮度累加到
+// 它所在的像素，最后乘以 b，图像的亮度与路径追踪一致。
+// samples_per_pixel 是平均每个像素的变异次数，路径贡献由路径追踪计算。
+pub struct Mlt {
+    pub bootstrap_samples: usize,
+    pub chains: usize,
+    // 小步变异的标准差。
+    pub sigma: f64,
+    pub large_step_probability: f64,
+}
+
+impl Default for Mlt {
+    fn default() -> Self {
+        Self {
+            bootstrap_samples: 100000,
+            chains: 1000,
+            sigma: 0.01,
+            large_step_probability: 0.3,
+        }
+    }
+}
+
+impl Mlt {
+    pub fn with_bootstrap_samples(mut self, bootstrap_samples: usize) -> Self {
+        self.bootstrap_samples = bootstrap_samples.max(1);
+        self
+    }
+
+    pub fn with_chains(mut self, chains: usize) -> Self {
+        self.chains = chains.max(1);
+        self
+    }
+
+    pub fn with_sigma(mut self, sigma: f64) -> Self {
+        self.sigma = sigma;
+        self
+    }
+
+    pub fn with_large_step_probability(mut self, large_step_probability: f64) -> Self {
+        self.large_step_probability = large_step_probability.clamp(0.0, 1.0);
+        self
+    }
+
+    // 用当前线程的采样器取一条路径：前两个随机数决定图像上的位置，其余的由相机和路径追踪取用。
+    fn path_sample(
+        &self,
+        camera: &Camera,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> PathSample {
+        let x = rtweekend::random_double() * camera.image_width as f64;
+        let y = rtweekend::random_double() * camera.image_height() as f64;
+        let r = camera.raster_ray(x, y);
+        let radiance = PathTracer.ray_color(camera, &r, world, lights, film);
+        PathSample {
+            x,
+            y,
+            radiance: if radiance.x().is_finite()
+                && radiance.y().is_finite()
+                && radiance.z().is_finite()
+            {
+                radiance
+            } else {
+                Color::default()
+            },
+        }
+    }
+
+    fn run(
+        &self,
+        sampler: MltSampler,
+        camera: &Camera,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> (MltSampler, PathSample) {
+        sampler::with_sampler(sampler, || self.path_sample(camera, world, lights, film))
+    }
+}
+
+impl Integrator for Mlt {
+    // 单独追踪一条光线时没有马尔可夫链可用，退化为路径追踪。
+    fn ray_color(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> Color {
+        PathTracer.ray_color(camera, r, world, lights, film)
+    }
+
+    fn render(
+        &self,
+        camera: &Camera,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+    ) -> Option<Vec<Color>> {
+        let width = camera.image_width;
+        let height = camera.image_height();
+        let pixel_count = (width * height) as usize;
+        // 路径追踪不会用到 film，只是为了满足 ray_color 的参数。
+        let film = Film::new(width, height);
+        let seed = rand::random::<u64>();
+
+        // 同一个种子在链的起点重现 bootstrap 时的路径。
+        let weights: Vec<f64> = (0..self.bootstrap_samples)
+            .into_par_iter()
+            .map(|k| {
+                let sampler = MltSampler::new(
+                    seed.wrapping_add(k as u64),
+                    self.sigma,
+                    self.large_step_probability,
+                );
+                self.run(sampler, camera, world, lights, &film)
+                    .1
+                    .importance()
+            })
+            .collect();
+        let b = weights.iter().sum::<f64>() / weights.len() as f64;
+        if b <= 0.0 {
+            return Some(vec![Color::default(); pixel_count]);
+        }
+        let bootstrap = AliasTable::new(&weights);
+
+        let mutations = camera.samples_per_pixel as u64 * pixel_count as u64;
+        let progress = camera.progress_bar(self.chains as u64);
+        let image = (0..self.chains)
+            .into_par_iter()
+            .fold(
+                || vec![Color::default(); pixel_count],
+                |mut image, chain| {
+                    let chain_mutations = mutations / self.chains as u64
+                        + u64::from((chain as u64) < mutations % self.chains as u64);
+                    let k = bootstrap.sample();
+                    let sampler = MltSampler::new(
+                        seed.wrapping_add(k as u64),
+                        self.sigma,
+                        self.large_step_probability,
+                    );
+                    let (mut sampler, mut current) =
+                        self.run(sampler, camera, world, lights, &film);
+                    if current.importance() <= 0.0 {
+                        progress.inc(1);
+                        return image;
+                    }
+                    sampler.reseed(rand::random::<u64>());
+
+                    let mut splat = |p: &PathSample, weight: f64| {
+                        let i = (p.x as u32).min(width - 1);
+                        let j = (p.y as u32).min(height - 1);
+                        image[(j * width + i) as usize] += p.radiance * weight;
+                    };
+                    for _ in 0..chain_mutations {
+                        sampler.start_iteration();
+                        let (next_sampler, proposed) =
+                            self.run(sampler, camera, world, lights, &film);
+                        sampler = next_sampler;
+
+                        // 按接受概率把两个状态的贡献都累加上（期望值），减少被拒绝的提议造成的浪费。
+                        let accept = (proposed.importance() / current.importance()).min(1.0);
+                        if accept > 0.0 {
+                            splat(&proposed, accept / proposed.importance());
+                        }
+                        splat(&current, (1.0 - accept) / current.importance());
+
+                        if rtweekend::random_double() < accept {
+                            current = proposed;
+                            sampler.accept();
+                        } else {
+                            sampler.reject();
+                        }
+                    }
+                    progress.inc(1);
+                    image
+                },
+            )
+            .reduce(
+                || vec![Color::default(); pixel_count],
+                |mut a, b| {
+                    for (a, b) in a.iter_mut().zip(b) {
+                        *a += b;
+                    }
+                    a
+                },
+            );
+        progress.finish();
+
+        // 每个像素平均 samples_per_pixel 次变异，累加的值乘以 b 就是 samples_per_pixel 个样本之和。
+        Some(image.into_iter().map(|c| c * b).collect())
+    }
+}
@@ -1,15 +1,101 @@
-use super::triangle::Triangle;
+use super::triangle::{self, Triangle};
 use std::sync::Arc;
 
 use super::Color;
-use super::hittable;
-use super::material::{Lambertian, Material};
-use super::texture::ImageTexture;
-use super::vec3::{Point3, Vec3};
+use super::mapping::{AlphaMap, EmissionMap, NormalMap};
+use super::material::{DiffuseLight, Lambertian, Material};
+use super::rtw_stb_image::ColorSpace;
+use super::texture::{self, ImageTexture, SolidColor, Texture};
+use super::vec3::{self, Vec3};
 use crate::hittable_list::HittableList;
 use std::path::Path;
 use tobj::LoadOptions;
 
+fn texture_path(file_path: &str, texture_filename: &str) -> String {
+    let base_path = Path::new(file_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    base_path
+        .join(texture_filename)
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+fn make_material(file_path: &str, mat: &tobj::Material) -> Arc<dyn Material> {
+    // 每个 MTL 材质只创建一次，所有使用它的三角形共享同一份纹理数据。
+    let mut diffuse_alpha = None;
+    let base: Arc<dyn Material> = if let Some(texture_filename) = &mat.diffuse_texture {
+        let texture = ImageTexture::new(&texture_path(file_path, texture_filename));
+        if texture.has_alpha() {
+            diffuse_alpha = Some(texture.clone());
+        }
+        Arc::new(Lambertian::new_with_texture(texture))
+    } else {
+        let color = match mat.diffuse {
+            Some(c) => Color::new(c[0] as f64, c[1] as f64, c[2] as f64),
+            None => Color::new(0.8, 0.8, 0.8),
+        };
+        Arc::new(Lambertian::new(color))
+    };
+
+    // map_Bump / bump 被 tobj 解析为 normal_texture，norm 则留在 unknown_param 中，都按切线空间法线贴图处理。
+    let normal_texture = mat
+        .normal_texture
+        .as_ref()
+        .or_else(|| mat.unknown_param.get("norm"));
+    let mut material = if let Some(normal_filename) = normal_texture {
+        let normal_map = ImageTexture::new_with_color_space(
+            &texture_path(file_path, normal_filename),
+            ColorSpace::Linear,
+        );
+        Arc::new(NormalMap::new(base, normal_map))
+    } else {
+        base
+    };
+
+    // 不透明度依次来自漫反射贴图的 alpha 通道、map_d 灰度贴图和标量 d，三者相乘。
+    if let Some(texture) = diffuse_alpha {
+        material = Arc::new(AlphaMap::new(material, texture));
+    }
+    if let Some(mask_filename) = &mat.dissolve_texture {
+        let mask = ImageTexture::new_with_color_space(
+            &texture_path(file_path, mask_filename),
+            ColorSpace::Linear,
+        );
+        material = Arc::new(AlphaMap::new_with_mask(material, mask));
+    }
+    if let Some(dissolve) = mat.dissolve.filter(|&d| d < 1.0) {
+        let mask = SolidColor::new_with_rgb(dissolve as f64, dissolve as f64, dissolve as f64);
+        material = Arc::new(AlphaMap::new_with_mask(material, mask));
+    }
+
+    // Ke 为发光颜色，map_Ke 为发光贴图，两者同时存在时相乘。
+    let emission = mat
+        .emissive
+        .map(|ke| Color::new(ke[0] as f64, ke[1] as f64, ke[2] as f64))
+        .filter(|ke| !ke.near_zero());
+    let emission_texture = mat.unknown_param.get("map_Ke");
+    if emission.is_some() || emission_texture.is_some() {
+        let texture: Arc<dyn Texture> = match emission_texture {
+            Some(filename) => Arc::new(ImageTexture::new(&texture_path(file_path, filename))),
+            None => texture::constant(Color::one()),
+        };
+        let mut light = DiffuseLight::new(texture);
+        light.scale = emission.unwrap_or(Color::one());
+        material = Arc::new(EmissionMap::new_with_light(material, light));
+    }
+    material
+}
+
+fn vertex(data: &[f32], index: usize) -> Vec3 {
+    Vec3::new(
+        data[3 * index] as f64,
+        data[3 * index + 1] as f64,
+        data[3 * index + 2] as f64,
+    )
+}
+
 pub fn load_model<M: Material + Clone + 'static>(
     file_path: &str,
     default_material: M,
@@ -28,25 +114,12 @@ pub fn load_model<M: Material + Clone + 'static>(
     .expect("Failed to load .obj file");
 
     let tobj_materials = tobj_materials_res.expect("Failed to load .mtl file");
-    let mut materials: Vec<Arc<dyn Material>> = Vec::new();
-    for mat in tobj_materials.clone() {
-        let material: Arc<dyn Material> = if let Some(texture_filename) = mat.diffuse_texture {
-            let base_path = Path::new(file_path)
-                .parent()
-                .unwrap_or_else(|| Path::new(""));
-            let texture_path = base_path.join(texture_filename);
-
-            let texture = ImageTexture::new(texture_path.to_str().unwrap());
-            Arc::new(Lambertian::new_with_texture(texture))
-        } else {
-            let color = match mat.diffuse {
-                Some(c) => Color::new(c[0] as f64, c[1] as f64, c[2] as f64),
-                None => Color::new(0.8, 0.8, 0.8),
-            };
-            Arc::new(Lambertian::new(color))
-        };
-        materials.push(material);
-    }
+    let materials: Vec<Arc<dyn Material>> = tobj_materials
+        .iter()
+        .map(|mat| make_material(file_path, mat))
+        .collect();
+    let default_material: Arc<dyn Material> = Arc::new(default_material);
+
     for model in tobj_models {
         let mesh = &model.mesh;
         let positions = &mesh.positions;
@@ -56,147 +129,97 @@ pub fn load_model<M: Material + Clone + 'static>(
         let has_normals = !normals.is_empty();
         let has_texcoords = !texcoords.is_empty();
 
-        /*let material = match mesh.material_id {
+        let material = match mesh.material_id {
             Some(id) => materials[id].clone(),
-            None => Arc::new(default_material.clone()),
-        };*/
-
-        let default_normal = Vec3::new(0.0, 1.0, 0.0);
-        //let default_uv = (0.0, 0.0);
-
-        for i in (0..mesh.indices.len()).step_by(3) {
-            let i0 = mesh.indices[i] as usize;
-            let i1 = mesh.indices[i + 1] as usize;
-            let i2 = mesh.indices[i + 2] as usize;
-
-            let p0 = Point3::new(
-                positions[3 * i0] as f64,
-                positions[3 * i0 + 1] as f64,
-                positions[3 * i0 + 2] as f64,
-            );
-            let p1 = Point3::new(
-                positions[3 * i1] as f64,
-                positions[3 * i1 + 1] as f64,
-                positions[3 * i1 + 2] as f64,
-            );
-            let p2 = Point3::new(
-                positions[3 * i2] as f64,
-                positions[3 * i2 + 1] as f64,
-                positions[3 * i2 + 2] as f64,
-            );
+            None => default_material.clone(),
+        };
 
-            let n0 = if has_normals {
-                Vec3::new(
-                    normals[3 * i0] as f64,
-                    normals[3 * i0 + 1] as f64,
-                    normals[3 * i0 + 2] as f64,
+        let uv = |index: usize| {
+            if has_texcoords {
+                (
+                    texcoords[2 * index] as f64,
+                    1.0 - texcoords[2 * index + 1] as f64,
                 )
-            } else {
-                default_normal
-            };
-            let n1 = if has_normals {
-                Vec3::new(
-                    normals[3 * i1] as f64,
-                    normals[3 * i1 + 1] as f64,
-                    normals[3 * i1 + 2] as f64,
-                )
-            } else {
-                default_normal
-            };
-            let n2 = if has_normals {
-                Vec3::new(
-                    normals[3 * i2] as f64,
-                    normals[3 * i2 + 1] as f64,
-                    normals[3 * i2 + 2] as f64,
-                )
-            } else {
-                default_normal
-            };
-
-            let uv0 = if has_texcoords {
-                (texcoords[2 * i0] as f64, 1.0 - texcoords[2 * i0 + 1] as f64)
             } else {
                 (0.0, 0.0)
-            };
-            let uv1 = if has_texcoords {
-                (texcoords[2 * i1] as f64, 1.0 - texcoords[2 * i1 + 1] as f64)
-            } else {
-                (0.0, 0.0)
-            };
-            let uv2 = if has_texcoords {
-                (texcoords[2 * i2] as f64, 1.0 - texcoords[2 * i2 + 1] as f64)
-            } else {
-                (0.0, 0.0)
-            };
-            let triangle_to_add: Arc<dyn hittable::Hittable>;
-
-            // --- 核心的分发逻辑在这里 ---
-            match mesh.material_id {
-                Some(id) => {
-                    // 根据ID获取tobj的材质信息
-                    let mat_info = &tobj_materials[id];
-
-                    if let Some(texture_filename) = &mat_info.diffuse_texture {
-                        // 情况1：有漫反射纹理，创建 Lambertian<ImageTexture>
-                        let base_path = Path::new(file_path)
-                            .parent()
-                            .unwrap_or_else(|| Path::new(""));
-                        let texture_path = base_path.join(texture_filename);
-                        let texture = ImageTexture::new(texture_path.to_str().unwrap());
-
-                        let concrete_material = Lambertian::new_with_texture(texture);
-
-                        triangle_to_add = Arc::new(Triangle::new(
-                            p0,
-                            p1,
-                            p2,
-                            n0,
-                            n1,
-                            n2,
-                            uv0,
-                            uv1,
-                            uv2,
-                            concrete_material,
-                        ));
-                    } else {
-                        // 情况2：没有纹理，使用漫反射颜色，创建 Lambertian<SolidColor>
-                        let color = mat_info.diffuse.map_or(Color::new(0.8, 0.8, 0.8), |c| {
-                            Color::new(c[0] as f64, c[1] as f64, c[2] as f64)
-                        });
-
-                        let concrete_material = Lambertian::new(color);
-
-                        triangle_to_add = Arc::new(Triangle::new(
-                            p0,
-                            p1,
-                            p2,
-                            n0,
-                            n1,
-                            n2,
-                            uv0,
-                            uv1,
-                            uv2,
-                            concrete_material,
-                        ));
-                    }
+            }
+        };
+
+        // 在共享顶点上累加各个面的 dp/du，得到平滑的顶点切线。各个面的 dp/du 先归一化，
+        // 长度只取决于纹理的缩放，不应影响平均；纹理坐标退化的面没有确定的切线，跳过。
+        let vertex_count = positions.len() / 3;
+        let mut tangents = vec![Vec3::zero(); vertex_count];
+        if has_texcoords {
+            for face in mesh.indices.chunks_exact(3) {
+                let (i0, i1, i2) = (face[0] as usize, face[1] as usize, face[2] as usize);
+                let (p0, p1, p2) = (
+                    vertex(positions, i0),
+                    vertex(positions, i1),
+                    vertex(positions, i2),
+                );
+                let Some((dpdu, _)) = triangle::uv_derivatives(p0, p1, p2, uv(i0), uv(i1), uv(i2))
+                else {
+                    continue;
+                };
+                if dpdu.near_zero() {
+                    continue;
                 }
-                None => {
-                    triangle_to_add = Arc::new(Triangle::new(
-                        p0,
-                        p1,
-                        p2,
-                        n0,
-                        n1,
-                        n2,
-                        uv0,
-                        uv1,
-                        uv2,
-                        default_material.clone(),
-                    ));
+                let dpdu = vec3::unit_vector(dpdu);
+                for index in [i0, i1, i2] {
+                    tangents[index] += dpdu;
                 }
             }
+        }
+
+        for face in mesh.indices.chunks_exact(3) {
+            let (i0, i1, i2) = (face[0] as usize, face[1] as usize, face[2] as usize);
+
+            let p0 = vertex(positions, i0);
+            let p1 = vertex(positions, i1);
+            let p2 = vertex(positions, i2);
+
+            // 没有顶点法线时使用面法线，避免所有三角形都朝向同一个方向。
+            let face_normal = vec3::cross(p1 - p0, p2 - p0);
+            if face_normal.near_zero() {
+                continue;
+            }
+            let face_normal = vec3::unit_vector(face_normal);
+            let normal = |index: usize| {
+                if has_normals {
+                    vertex(normals, index)
+                } else {
+                    face_normal
+                }
+            };
+            let (n0, n1, n2) = (normal(i0), normal(i1), normal(i2));
+
+            let mut triangle = Triangle::new(
+                p0,
+                p1,
+                p2,
+                n0,
+                n1,
+                n2,
+                uv(i0),
+                uv(i1),
+                uv(i2),
+                material.clone(),
+            );
+
+            if has_texcoords {
+                // Gram-Schmidt 正交化到顶点法线上。
+                let orthogonal = |t: Vec3, n: Vec3| {
+                    let n = vec3::unit_vector(n);
+                    t - vec3::dot(t, n) * n
+                };
+                triangle.set_tangents(
+                    orthogonal(tangents[i0], n0),
+                    orthogonal(tangents[i1], n1),
+                    orthogonal(tangents[i2], n2),
+                );
+            }
 
-            models.add(triangle_to_add);
+            models.add(Arc::new(triangle));
         }
     }
     println!("Model loaded with {} triangles.", models.objects.len());
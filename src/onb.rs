@@ -2,7 +2,7 @@ use std::ops::{Index, IndexMut};
 
 use super::vec3::{self, Vec3};
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct Onb {
     pub axis: [Vec3; 3],
 }
@@ -25,6 +25,15 @@ impl Onb {
         a.x() * self.u() + a.y() * self.v() + a.z() * self.w()
     }
 
+    // local_v 的逆变换：把世界坐标中的向量投影到这组基上。
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(
+            vec3::dot(a, self.u()),
+            vec3::dot(a, self.v()),
+            vec3::dot(a, self.w()),
+        )
+    }
+
     pub fn new_from_w(w: Vec3) -> Self {
         let unit_w = vec3::unit_vector(w);
         let a = if unit_w.x().abs() > 0.9 {
@@ -38,6 +47,20 @@ impl Onb {
             axis: [u, v, unit_w],
         }
     }
+
+    pub fn new_from_w_u(w: Vec3, u: Vec3) -> Self {
+        // 以 w 为法线、尽量沿 u 方向（例如切线 dp/du）构造正交基。u 与 w 平行或为零时退回 new_from_w。
+        let unit_w = vec3::unit_vector(w);
+        let u = u - vec3::dot(u, unit_w) * unit_w;
+        if u.near_zero() {
+            return Self::new_from_w(w);
+        }
+        let u = vec3::unit_vector(u);
+        let v = vec3::cross(unit_w, u);
+        Self {
+            axis: [u, v, unit_w],
+        }
+    }
 }
 
 impl Index<usize> for Onb {
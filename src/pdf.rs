@@ -9,6 +9,13 @@ pub trait Pdf: Send + Sync {
     fn generate(&self) -> vec3::Vec3;
 }
 
+// 多重重要性采样的幂启发式（指数为 2）：用 pdf_f 采样得到的样本的权重。
+pub fn power_heuristic(pdf_f: f64, pdf_g: f64) -> f64 {
+    let f = pdf_f * pdf_f;
+    let g = pdf_g * pdf_g;
+    if f + g > 0.0 { f / (f + g) } else { 0.0 }
+}
+
 pub struct SpherePdf;
 
 impl Pdf for SpherePdf {
@@ -67,24 +74,76 @@ impl Pdf for HittablePdf {
 
 pub struct MixturePdf {
     pub p: [Arc<dyn Pdf>; 2],
+    // 选择 p[0] 的概率。
+    pub weight: f64,
 }
 
 impl MixturePdf {
     pub fn new(p0: Arc<dyn Pdf>, p1: Arc<dyn Pdf>) -> Self {
-        Self { p: [p0, p1] }
+        Self::new_with_weight(p0, p1, 0.5)
+    }
+
+    pub fn new_with_weight(p0: Arc<dyn Pdf>, p1: Arc<dyn Pdf>, weight: f64) -> Self {
+        Self {
+            p: [p0, p1],
+            weight,
+        }
     }
 }
 
 impl Pdf for MixturePdf {
     fn value(&self, direction: vec3::Vec3) -> f64 {
-        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+        self.weight * self.p[0].value(direction) + (1.0 - self.weight) * self.p[1].value(direction)
     }
 
     fn generate(&self) -> vec3::Vec3 {
-        if rtweekend::random_double_range(0.0, 1.0) < 0.5 {
+        if rtweekend::random_double_range(0.0, 1.0) < self.weight {
             self.p[0].generate()
         } else {
             self.p[1].generate()
         }
     }
 }
+
+// 按权重在多个 PDF 之间选择，权重会被归一化，权重为零的分量不会被采样。
+pub struct WeightedPdf {
+    pub pdfs: Vec<(f64, Arc<dyn Pdf>)>,
+}
+
+impl WeightedPdf {
+    pub fn new(pdfs: Vec<(f64, Arc<dyn Pdf>)>) -> Self {
+        let total: f64 = pdfs.iter().map(|(w, _)| w.max(0.0)).sum();
+        let pdfs = if total > 0.0 {
+            pdfs.into_iter()
+                .filter(|(w, _)| *w > 0.0)
+                .map(|(w, pdf)| (w / total, pdf))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self { pdfs }
+    }
+}
+
+impl Pdf for WeightedPdf {
+    fn value(&self, direction: vec3::Vec3) -> f64 {
+        self.pdfs
+            .iter()
+            .map(|(w, pdf)| w * pdf.value(direction))
+            .sum()
+    }
+
+    fn generate(&self) -> vec3::Vec3 {
+        let mut xi = rtweekend::random_double();
+        for (w, pdf) in self.pdfs.iter() {
+            if xi < *w {
+                return pdf.generate();
+            }
+            xi -= w;
+        }
+        match self.pdfs.last() {
+            Some((_, pdf)) => pdf.generate(),
+            None => vec3::Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+}
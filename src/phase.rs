@@ -0,0 +1,198 @@
+use super::onb::Onb;
+use super::pdf::Pdf;
+use super::rtweekend;
+use super::vec3::{self, Vec3};
+
+// 参与介质的相函数。cos_theta 是入射光线的传播方向与散射方向夹角的余弦，
+// 相函数对立体角归一化，并且绕传播方向旋转对称，所以只需要采样 cos_theta。
+pub trait PhaseFunction: Send + Sync {
+    fn p(&self, cos_theta: f64) -> f64;
+    fn sample_cos_theta(&self) -> f64;
+}
+
+// Henyey–Greenstein 相函数。g 为平均余弦，g > 0 时前向散射，g = 0 时各向同性。
+#[derive(Clone, Copy)]
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        Self {
+            g: g.clamp(-0.999, 0.999),
+        }
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn p(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * rtweekend::PI * denom * denom.sqrt())
+    }
+
+    fn sample_cos_theta(&self) -> f64 {
+        let g = self.g;
+        let xi = rtweekend::random_double();
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+// 两个 Henyey–Greenstein 波瓣的混合，常用一个前向和一个后向的波瓣描述云和尘埃。
+#[derive(Clone, Copy)]
+pub struct DoubleHenyeyGreenstein {
+    forward: HenyeyGreenstein,
+    backward: HenyeyGreenstein,
+    weight: f64,
+}
+
+impl DoubleHenyeyGreenstein {
+    // weight 为第一个波瓣 g1 所占的比例。
+    pub fn new(g1: f64, g2: f64, weight: f64) -> Self {
+        Self {
+            forward: HenyeyGreenstein::new(g1),
+            backward: HenyeyGreenstein::new(g2),
+            weight: weight.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn p(&self, cos_theta: f64) -> f64 {
+        self.weight * self.forward.p(cos_theta) + (1.0 - self.weight) * self.backward.p(cos_theta)
+    }
+
+    fn sample_cos_theta(&self) -> f64 {
+        if rtweekend::random_double() < self.weight {
+            self.forward.sample_cos_theta()
+        } else {
+            self.backward.sample_cos_theta()
+        }
+    }
+}
+
+// 瑞利散射，远小于波长的粒子（如空气分子）的相函数。
+#[derive(Clone, Copy, Default)]
+pub struct Rayleigh;
+
+impl PhaseFunction for Rayleigh {
+    fn p(&self, cos_theta: f64) -> f64 {
+        3.0 / (16.0 * rtweekend::PI) * (1.0 + cos_theta * cos_theta)
+    }
+
+    fn sample_cos_theta(&self) -> f64 {
+        // 反解分布函数 (cos^3 + 3 cos + 4) / 8 = xi，用卡尔达诺公式求这个三次方程的实根。
+        let q = 4.0 * rtweekend::random_double() - 2.0;
+        let u = (q + (q * q + 1.0).sqrt()).cbrt();
+        (u - 1.0 / u).clamp(-1.0, 1.0)
+    }
+}
+
+// 按相函数对散射方向的采样，w 为入射光线的传播方向。
+pub struct PhasePdf<P: PhaseFunction> {
+    uvw: Onb,
+    phase: P,
+}
+
+impl<P: PhaseFunction> PhasePdf<P> {
+    pub fn new(w: Vec3, phase: P) -> Self {
+        Self {
+            uvw: Onb::new_from_w(w),
+            phase,
+        }
+    }
+}
+
+impl<P: PhaseFunction> Pdf for PhasePdf<P> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.phase
+            .p(vec3::dot(vec3::unit_vector(direction), self.uvw.w()))
+    }
+
+    fn generate(&self) -> Vec3 {
+        let cos_theta = self.phase.sample_cos_theta();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * rtweekend::PI * rtweekend::random_double();
+        self.uvw
+            .local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 200000;
+    const BINS: usize = 20;
+
+    fn check<P: PhaseFunction>(name: &str, phase: P) {
+        let w = vec3::unit_vector(Vec3::new(0.3, -0.5, 0.8));
+        let pdf = PhasePdf::new(w, phase);
+
+        // 在球面上均匀采样方向，value 的积分应当为 1。允许的误差为标准误差的 5 倍。
+        let (mut sum, mut sum_squares) = (0.0, 0.0);
+        for _ in 0..SAMPLES {
+            let f = 4.0 * rtweekend::PI * pdf.value(vec3::random_unit_vector());
+            sum += f;
+            sum_squares += f * f;
+        }
+        let n = SAMPLES as f64;
+        let mean = sum / n;
+        let error = ((sum_squares / n - mean * mean) / n).sqrt();
+        assert!(
+            (mean - 1.0).abs() < 5.0 * error + 1e-3,
+            "{name}: value integrates to {mean} ± {error}"
+        );
+
+        // generate 得到的 cos_theta 的分布与 value 在各个区间上的积分一致。
+        let mut counts = [0usize; BINS];
+        for _ in 0..SAMPLES {
+            let cos_theta = vec3::dot(vec3::unit_vector(pdf.generate()), w);
+            let bin = (((cos_theta + 1.0) / 2.0 * BINS as f64) as usize).min(BINS - 1);
+            counts[bin] += 1;
+        }
+        for (bin, &count) in counts.iter().enumerate() {
+            let steps = 100;
+            let width = 2.0 / (BINS * steps) as f64;
+            let expected: f64 = (0..steps)
+                .map(|k| {
+                    let cos_theta = -1.0 + (bin * steps + k) as f64 * width + 0.5 * width;
+                    2.0 * rtweekend::PI * phase_value(&pdf, w, cos_theta) * width
+                })
+                .sum();
+            let frequency = count as f64 / n;
+            assert!(
+                (frequency - expected).abs() < 5.0 * (expected / n).sqrt() + 1e-4,
+                "{name}: bin {bin} sampled {frequency}, expected {expected}"
+            );
+        }
+    }
+
+    // 与 w 夹角的余弦为 cos_theta 的方向上的 value。
+    fn phase_value<P: PhaseFunction>(pdf: &PhasePdf<P>, w: Vec3, cos_theta: f64) -> f64 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let uvw = Onb::new_from_w(w);
+        pdf.value(uvw.local(sin_theta, 0.0, cos_theta))
+    }
+
+    #[test]
+    fn henyey_greenstein_is_normalized() {
+        for g in [0.0, 0.7, -0.5] {
+            check(&format!("HG g = {g}"), HenyeyGreenstein::new(g));
+        }
+    }
+
+    #[test]
+    fn double_henyey_greenstein_is_normalized() {
+        check("double HG", DoubleHenyeyGreenstein::new(0.8, -0.3, 0.7));
+    }
+
+    #[test]
+    fn rayleigh_is_normalized() {
+        check("Rayleigh", Rayleigh);
+    }
+}
@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use super::color::{self, Color};
+use super::hittable::HitRecord;
+use super::material::{Material, ScatterRecord};
+use super::microfacet::{
+    self, MicrofacetDielectric, MicrofacetDielectricPdf, MicrofacetReflectionPdf, TrowbridgeReitz,
+};
+use super::onb::Onb;
+use super::pdf::{CosinePdf, Pdf, WeightedPdf};
+use super::ray::Ray;
+use super::rtweekend;
+use super::spectrum;
+use super::texture::{self, Texture};
+use super::vec3::{self, Point3, Vec3};
+
+// 类似 Disney / glTF 的“原理化”材质。每个参数都是一个纹理，标量参数取纹理通道的平均值。
+// 各层从上到下依次为：清漆层、镜面反射层（金属或电介质）、透射层和漫反射加光泽（sheen）层。
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub anisotropy: Arc<dyn Texture>,
+    // 电介质部分的镜面反射强度，0.5 对应 F0 = 0.04。
+    pub specular: Arc<dyn Texture>,
+    // 光泽层的颜色和强度。
+    pub sheen: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: f64,
+    pub emission: Arc<dyn Texture>,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: texture::constant(Color::new(0.8, 0.8, 0.8)),
+            metallic: texture::scalar(0.0),
+            roughness: texture::scalar(0.5),
+            anisotropy: texture::scalar(0.0),
+            specular: texture::scalar(0.5),
+            sheen: texture::scalar(0.0),
+            clearcoat: texture::scalar(0.0),
+            clearcoat_roughness: texture::scalar(0.03),
+            transmission: texture::scalar(0.0),
+            ior: 1.5,
+            emission: texture::scalar(0.0),
+        }
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Self {
+        Self {
+            base_color: texture::constant(base_color),
+            ..Default::default()
+        }
+    }
+
+    pub fn new_with_texture<T: Texture + 'static>(base_color: T) -> Self {
+        Self {
+            base_color: Arc::new(base_color),
+            ..Default::default()
+        }
+    }
+}
+
+// 在命中点上求值后的参数，以及各层的采样概率。
+struct PrincipledLobes {
+    uvw: Onb,
+    wo: Vec3,
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular_f0: Color,
+    dielectric_f0: f64,
+    sheen: Color,
+    clearcoat: f64,
+    transmission: f64,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+    dielectric: MicrofacetDielectric,
+    weights: [f64; 4],
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// 穿过电介质镜面层和清漆层后剩下的能量比例，漫反射和光泽层只能用这部分能量。
+fn layer_transmittance(dielectric_f0: f64, clearcoat: f64, cos_theta: f64) -> f64 {
+    let specular = dielectric_f0 + (1.0 - dielectric_f0) * schlick_weight(cos_theta);
+    let coat = 0.25 * clearcoat * microfacet::fresnel_dielectric(cos_theta, 1.5);
+    (1.0 - specular) * (1.0 - coat)
+}
+
+impl Principled {
+    fn lobes(&self, r_in: &Ray, rec: &HitRecord) -> PrincipledLobes {
+        let (u, v, p) = (rec.u, rec.v, rec.p);
+        let uvw = Onb::new_from_w_u(rec.normal, rec.dpdu);
+        let mut wo = uvw.to_local(-vec3::unit_vector(r_in.direction()));
+        if wo.z() < 1e-4 {
+            wo[2] = 1e-4;
+            wo = vec3::unit_vector(wo);
+        }
+
+        let hero = r_in.hero_wavelength();
+        let base_color = self.base_color.value(u, v, p);
+        let metallic = texture::scalar_value(&self.metallic, u, v, p).clamp(0.0, 1.0);
+        let roughness = texture::scalar_value(&self.roughness, u, v, p).clamp(0.0, 1.0);
+        let anisotropy = texture::scalar_value(&self.anisotropy, u, v, p);
+        let specular = texture::scalar_value(&self.specular, u, v, p).max(0.0);
+        let sheen = self.sheen.value(u, v, p);
+        let clearcoat = texture::scalar_value(&self.clearcoat, u, v, p).clamp(0.0, 1.0);
+        let clearcoat_roughness =
+            texture::scalar_value(&self.clearcoat_roughness, u, v, p).clamp(0.0, 1.0);
+        let transmission =
+            texture::scalar_value(&self.transmission, u, v, p).clamp(0.0, 1.0) * (1.0 - metallic);
+
+        let dielectric_f0 = 0.08 * specular;
+        let specular_f0 = |base_color: Color| {
+            Color::new(dielectric_f0, dielectric_f0, dielectric_f0) * (1.0 - metallic)
+                + base_color * metallic
+        };
+
+        let distribution = TrowbridgeReitz::from_roughness(roughness, anisotropy);
+        let clearcoat_distribution = TrowbridgeReitz::from_roughness(clearcoat_roughness, 0.0);
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+        let dielectric = MicrofacetDielectric::new(distribution, eta);
+
+        // 采样概率按各层在出射方向上的大致反照率分配：
+        // 镜面反射、漫反射（含光泽层）、透射、清漆。
+        let opaque = 1.0 - transmission;
+        let diffuse =
+            (1.0 - metallic) * opaque * layer_transmittance(dielectric_f0, clearcoat, wo.z());
+        let weights = [
+            opaque * color::luminance(microfacet::fresnel_schlick(specular_f0(base_color), wo.z())),
+            diffuse * (color::luminance(base_color) + color::luminance(sheen)),
+            transmission,
+            0.25 * clearcoat * microfacet::fresnel_dielectric(wo.z(), 1.5),
+        ];
+
+        // 采样概率按 RGB 计算，着色用的颜色在这之后上采样到光线的波长上。
+        let base_color = spectrum::from_rgb(base_color, hero);
+        PrincipledLobes {
+            uvw,
+            wo,
+            base_color,
+            metallic,
+            roughness,
+            specular_f0: specular_f0(base_color),
+            dielectric_f0,
+            sheen: spectrum::from_rgb(sheen, hero),
+            clearcoat,
+            transmission,
+            distribution,
+            clearcoat_distribution,
+            dielectric,
+            weights,
+        }
+    }
+
+    fn lobe_pdf(lobes: &PrincipledLobes, normal: Vec3) -> WeightedPdf {
+        let pdfs: Vec<(f64, Arc<dyn Pdf>)> = vec![
+            (
+                lobes.weights[0],
+                Arc::new(MicrofacetReflectionPdf::new(
+                    lobes.uvw,
+                    lobes.wo,
+                    lobes.distribution,
+                )),
+            ),
+            (lobes.weights[1], Arc::new(CosinePdf::new(normal))),
+            (
+                lobes.weights[2],
+                Arc::new(MicrofacetDielectricPdf::new(
+                    lobes.uvw,
+                    lobes.wo,
+                    lobes.dielectric,
+                )),
+            ),
+            (
+                lobes.weights[3],
+                Arc::new(MicrofacetReflectionPdf::new(
+                    lobes.uvw,
+                    lobes.wo,
+                    lobes.clearcoat_distribution,
+                )),
+            ),
+        ];
+        WeightedPdf::new(pdfs)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let lobes = self.lobes(r_in, rec);
+        srec.attenuation = lobes.base_color;
+        srec.pdf = Arc::new(Self::lobe_pdf(&lobes, rec.normal));
+        srec.skip_pdf = false;
+        true
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        if rec.front_face {
+            spectrum::from_rgb(self.emission.value(u, v, p), r_in.hero_wavelength())
+        } else {
+            Color::default()
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        !self.emission.is_black()
+    }
+
+    fn emitted_power(&self) -> f64 {
+        rtweekend::PI * color::luminance(self.emission.average())
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let lobes = self.lobes(r_in, rec);
+        Self::lobe_pdf(&lobes, rec.normal).value(scattered.direction())
+    }
+
+    fn bsdf_cos(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let lobes = self.lobes(r_in, rec);
+        let wo = lobes.wo;
+        let wi = lobes.uvw.to_local(vec3::unit_vector(scattered.direction()));
+
+        // 透射层同时贡献了透射部分的反射和折射，折射光被 base color 染色。
+        let mut result = Color::default();
+        if lobes.transmission > 0.0 {
+            let f = lobes.dielectric.bsdf_cos(wo, wi) * lobes.transmission;
+            result += if wi.z() < 0.0 {
+                lobes.base_color * f
+            } else {
+                Color::new(f, f, f)
+            };
+        }
+        if wi.z() <= 0.0 {
+            return result;
+        }
+
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return result;
+        }
+        let wm = vec3::unit_vector(wm);
+        let cos_theta_d = vec3::dot(wi, wm);
+        let opaque = 1.0 - lobes.transmission;
+
+        // GGX 镜面反射层。
+        let f = microfacet::fresnel_schlick(lobes.specular_f0, vec3::dot(wo, wm));
+        let d = lobes.distribution.d(wm);
+        let g = lobes.distribution.g(wo, wi);
+        result += f * (opaque * d * g / (4.0 * wo.z()));
+
+        // Burley 漫反射，粗糙表面在掠射角附近有回射。
+        // 入射和出射都要穿过上面的镜面层和清漆层，两个方向都乘上透过率以保持互易。
+        let diffuse_weight = (1.0 - lobes.metallic)
+            * opaque
+            * layer_transmittance(lobes.dielectric_f0, lobes.clearcoat, wo.z())
+            * layer_transmittance(lobes.dielectric_f0, lobes.clearcoat, wi.z());
+        if diffuse_weight > 0.0 {
+            let fl = schlick_weight(wi.z());
+            let fv = schlick_weight(wo.z());
+            let rr = 2.0 * lobes.roughness * cos_theta_d * cos_theta_d;
+            let lambert = (1.0 - 0.5 * fl) * (1.0 - 0.5 * fv);
+            let retro = rr * (fl + fv + fl * fv * (rr - 1.0));
+            result +=
+                lobes.base_color * (diffuse_weight * (lambert + retro) * wi.z() / rtweekend::PI);
+            result += lobes.sheen * (diffuse_weight * schlick_weight(cos_theta_d) * wi.z());
+        }
+
+        // 清漆层：折射率 1.5 的各向同性 GGX。
+        if lobes.clearcoat > 0.0 {
+            let f = microfacet::fresnel_dielectric(vec3::dot(wo, wm), 1.5);
+            let d = lobes.clearcoat_distribution.d(wm);
+            let g = lobes.clearcoat_distribution.g(wo, wi);
+            let c = 0.25 * lobes.clearcoat * f * d * g / (4.0 * wo.z());
+            result += Color::new(c, c, c);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 40000;
+
+    // 白色底色、带光泽层和清漆层的材质在 cos_theta 入射下的反照率，按材质自己的 PDF 重要性采样。
+    fn albedo(mat: &Principled, cos_theta: f64) -> f64 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let r = Ray::new(
+            Vec3::new(-sin_theta, 0.0, cos_theta),
+            Vec3::new(sin_theta, 0.0, -cos_theta),
+        );
+        let mut rec = HitRecord {
+            t: 1.0,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        rec.set_face_normal(&r, Vec3::new(0.0, 0.0, 1.0));
+
+        let mut srec = ScatterRecord::default();
+        assert!(mat.scatter(&r, &rec, &mut srec));
+        let mut sum = 0.0;
+        for _ in 0..SAMPLES {
+            let direction = srec.pdf.generate();
+            let pdf = srec.pdf.value(direction);
+            if pdf > 0.0 {
+                let scattered = Ray::new(rec.p, direction);
+                sum += color::luminance(mat.bsdf_cos(&r, &rec, &srec, &scattered)) / pdf;
+            }
+        }
+        sum / SAMPLES as f64
+    }
+
+    #[test]
+    fn diffuse_and_sheen_conserve_energy_under_coatings() {
+        for roughness in [0.2, 1.0] {
+            let mat = Principled {
+                base_color: texture::constant(Color::one()),
+                roughness: texture::scalar(roughness),
+                sheen: texture::constant(Color::one()),
+                clearcoat: texture::scalar(1.0),
+                ..Default::default()
+            };
+            for cos_theta in [0.9, 0.5, 0.2, 0.05] {
+                let a = albedo(&mat, cos_theta);
+                assert!(
+                    a < 1.0,
+                    "roughness {roughness}, cos {cos_theta}: albedo {a}"
+                );
+            }
+        }
+    }
+}
@@ -1,13 +1,14 @@
 use super::aabb::Aabb;
-use super::hittable::{HitRecord, Hittable};
+use super::hittable::{self, HitRecord, Hittable};
 use super::hittable_list::HittableList;
 use super::interval::Interval;
-use super::material::Material;
+use super::material::{self, Material};
 use super::ray::Ray;
 use super::rtweekend;
 use super::vec3::{self, Point3, Vec3};
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Quad<T: Material> {
     q: Point3,
     u: Vec3,
@@ -52,10 +53,8 @@ impl<T: Material> Quad<T> {
         rec.v = b;
         true
     }
-}
 
-impl<T: Material + Clone + 'static> Hittable for Quad<T> {
-    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+    fn intersect(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord, test_alpha: bool) -> bool {
         let denom = vec3::dot(self.normal, r.direction);
 
         if denom.abs() < 1e-8 {
@@ -70,14 +69,32 @@ impl<T: Material + Clone + 'static> Hittable for Quad<T> {
         let planar_hitpt_vector = intersection - self.q;
         let alpha = vec3::dot(self.w, vec3::cross(planar_hitpt_vector, self.v));
         let beta = vec3::dot(self.w, vec3::cross(self.u, planar_hitpt_vector));
+        // 透明贴图在写入命中记录之前测试，被忽略的命中不会影响 BVH 中已有的结果。
+        if test_alpha
+            && (0.0..=1.0).contains(&alpha)
+            && (0.0..=1.0).contains(&beta)
+            && !material::alpha_test(self.mat.alpha(alpha, beta, intersection))
+        {
+            return false;
+        }
         if !self.is_interior(alpha, beta, rec) {
             return false;
         }
 
         rec.t = t;
         rec.p = intersection;
-        rec.mat = Some(Arc::new(self.mat.clone()));
         rec.set_face_normal(r, self.normal);
+        rec.set_tangents(self.u, self.v);
+        true
+    }
+}
+
+impl<T: Material + Clone + 'static> Hittable for Quad<T> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.intersect(r, ray_t, rec, true) {
+            return false;
+        }
+        rec.mat = Some(Arc::new(self.mat.clone()));
         true
     }
 
@@ -85,12 +102,24 @@ impl<T: Material + Clone + 'static> Hittable for Quad<T> {
         &self.bbox
     }
 
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() {
+            lights.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn power(&self) -> f64 {
+        self.area * self.mat.emitted_power()
+    }
+
     fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        // random() 在整个四边形上均匀采样，所以这里的命中测试也不考虑透明贴图。
         let mut rec = HitRecord::default();
-        if !self.hit(
+        if !self.intersect(
             &Ray::new(origin, direction),
             &Interval::new(0.0001, f64::INFINITY),
             &mut rec,
+            false,
         ) {
             return 0.0;
         }
@@ -106,6 +135,28 @@ impl<T: Material + Clone + 'static> Hittable for Quad<T> {
             self.q + (rtweekend::random_double() * self.u) + (rtweekend::random_double() * self.v);
         p - origin
     }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let (a, b) = (rtweekend::random_double(), rtweekend::random_double());
+        rec.t = 0.0;
+        rec.p = self.q + a * self.u + b * self.v;
+        rec.normal = self.normal;
+        rec.geom_normal = self.normal;
+        rec.front_face = true;
+        rec.set_tangents(self.u, self.v);
+        (rec.u, rec.v) = (a, b);
+        rec.mat = Some(Arc::new(self.mat.clone()));
+        1.0 / self.area
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        let mut temp_rec = HitRecord::default();
+        if self.intersect(r, &hittable::emission_interval(rec.t), &mut temp_rec, false) {
+            1.0 / self.area
+        } else {
+            0.0
+        }
+    }
 }
 
 pub fn make_box<T: Material + Clone + 'static>(a: Point3, b: Point3, mat: T) -> HittableList {
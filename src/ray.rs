@@ -4,6 +4,12 @@ pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
     tm: f64,
+    // 光线携带的波长（纳米），0 表示尚未选择波长、按 RGB 三个通道一起传输。
+    wavelength: f64,
+    // 谱渲染时相机为整条路径采样的主波长，吞吐量的三个分量对应主波长和另外两个波长。0 表示 RGB 渲染。
+    hero_wavelength: f64,
+    // 光线所在的带优先级的嵌套物体（例如玻璃杯中的水）中最高的优先级，完整的内部栈由积分器保存。
+    interior_priority: Option<u32>,
 }
 
 impl Ray {
@@ -12,6 +18,9 @@ impl Ray {
             origin,
             direction,
             tm: 0.0,
+            wavelength: 0.0,
+            hero_wavelength: 0.0,
+            interior_priority: None,
         }
     }
 
@@ -20,6 +29,9 @@ impl Ray {
             origin,
             direction,
             tm,
+            wavelength: 0.0,
+            hero_wavelength: 0.0,
+            interior_priority: None,
         }
     }
 
@@ -38,4 +50,31 @@ impl Ray {
     pub fn time(&self) -> f64 {
         self.tm
     }
+
+    pub fn wavelength(&self) -> f64 {
+        self.wavelength
+    }
+
+    pub fn with_wavelength(mut self, wavelength: f64) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn hero_wavelength(&self) -> f64 {
+        self.hero_wavelength
+    }
+
+    pub fn with_hero_wavelength(mut self, hero_wavelength: f64) -> Self {
+        self.hero_wavelength = hero_wavelength;
+        self
+    }
+
+    pub fn interior_priority(&self) -> Option<u32> {
+        self.interior_priority
+    }
+
+    pub fn with_interior_priority(mut self, interior_priority: Option<u32>) -> Self {
+        self.interior_priority = interior_priority;
+        self
+    }
 }
@@ -1,18 +1,37 @@
 use stb_image::image;
+use std::sync::Arc;
 
-pub const BYTES_PER_PIXEL: usize = 3;
-static MAGENTA: [u8; BYTES_PER_PIXEL] = [255, 0, 255];
+use super::color;
+
+pub const CHANNELS_PER_PIXEL: usize = 4;
+static MAGENTA: [f32; CHANNELS_PER_PIXEL] = [1.0, 0.0, 1.0, 1.0];
+
+// 图像中存储的颜色所在的色彩空间。sRGB 用于反照率等颜色贴图，加载时解码为线性值；
+// Linear 用于法线、粗糙度等数据贴图，按原值读取。HDR 等浮点图像总是线性的。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
 
 #[derive(Default, Clone)]
 pub struct RtwImage {
-    data: Vec<u8>,
+    data: Arc<Vec<f32>>,
     image_width: usize,
     image_height: usize,
-    bytes_per_scanline: usize,
+    floats_per_scanline: usize,
+    color_space: ColorSpace,
+    is_hdr: bool,
+    has_alpha: bool,
 }
 
 impl RtwImage {
     pub fn new(image_filename: &str) -> Self {
+        Self::new_with_color_space(image_filename, ColorSpace::Srgb)
+    }
+
+    pub fn new_with_color_space(image_filename: &str, color_space: ColorSpace) -> Self {
         // 从指定的文件加载图像数据。如果定义了 RTW_IMAGES 环境变量，则仅在该目录中查找图像文件。
         // 如果未找到图像，则首先从当前目录，然后在 images/ 子目录中，然后在父级的 images/ 子目录中，
         // 依此类推，最多向上搜索六级。如果图像加载失败，width() 和 height() 将返回 0。
@@ -20,7 +39,10 @@ impl RtwImage {
         let filename = image_filename;
         let imagedir = std::env::var("RTW_IMAGES").unwrap_or_else(|_| String::from("images"));
 
-        let mut _self = Self::default();
+        let mut _self = Self {
+            color_space,
+            ..Default::default()
+        };
         if !imagedir.is_empty() && _self.load(&format!("{}/{}", imagedir, filename)) {
             return _self;
         }
@@ -53,19 +75,76 @@ impl RtwImage {
 
     pub fn load(&mut self, filename: &str) -> bool {
         // 从给定的文件名加载图像数据。如果加载成功，返回 true。
-        let load_result = image::load_with_depth(filename, BYTES_PER_PIXEL, false);
+        // 所有格式都统一存为 RGBA 浮点数，sRGB 图像在这里解码为线性值。
+        if Self::is_16_bit(filename) {
+            return self.load_16_bit(filename);
+        }
+
+        let load_result = image::load_with_depth(filename, CHANNELS_PER_PIXEL, false);
         match load_result {
             image::LoadResult::Error(_) => false,
             image::LoadResult::ImageU8(image) => {
-                assert_eq!(image.depth, BYTES_PER_PIXEL);
-                self.data = image.data;
-                self.image_width = image.width;
-                self.image_height = image.height;
-                self.bytes_per_scanline = image.depth * image.width;
+                assert_eq!(image.depth, CHANNELS_PER_PIXEL);
+                let data = image.data.iter().map(|&b| b as f32 / 255.0).collect();
+                self.set_data(data, image.width, image.height, false);
+                true
+            }
+            image::LoadResult::ImageF32(image) => {
+                assert_eq!(image.depth, CHANNELS_PER_PIXEL);
+                self.set_data(image.data, image.width, image.height, true);
+                true
+            }
+        }
+    }
+
+    fn is_16_bit(filename: &str) -> bool {
+        // stb_image 会把 16 位 PNG 截断为 8 位，所以先用 image 库检查位深。
+        use ::image::{ExtendedColorType, ImageDecoder, ImageReader};
+
+        ImageReader::open(filename)
+            .ok()
+            .and_then(|reader| reader.with_guessed_format().ok())
+            .and_then(|reader| reader.into_decoder().ok())
+            .is_some_and(|decoder| {
+                matches!(
+                    decoder.original_color_type(),
+                    ExtendedColorType::L16
+                        | ExtendedColorType::La16
+                        | ExtendedColorType::Rgb16
+                        | ExtendedColorType::Rgba16
+                )
+            })
+    }
+
+    fn load_16_bit(&mut self, filename: &str) -> bool {
+        match ::image::open(filename) {
+            Ok(image) => {
+                let image = image.to_rgba32f();
+                let (width, height) = image.dimensions();
+                self.set_data(image.into_raw(), width as usize, height as usize, false);
                 true
             }
-            image::LoadResult::ImageF32(_) => false,
+            Err(_) => false,
+        }
+    }
+
+    fn set_data(&mut self, mut data: Vec<f32>, width: usize, height: usize, is_hdr: bool) {
+        if !is_hdr && self.color_space == ColorSpace::Srgb {
+            // 只解码颜色通道，alpha 总是线性的。
+            data.chunks_exact_mut(CHANNELS_PER_PIXEL).for_each(|pixel| {
+                pixel[..3]
+                    .iter_mut()
+                    .for_each(|c| *c = color::srgb_to_linear(*c as f64) as f32);
+            });
         }
+        self.has_alpha = data
+            .chunks_exact(CHANNELS_PER_PIXEL)
+            .any(|pixel| pixel[3] < 1.0);
+        self.data = Arc::new(data);
+        self.image_width = width;
+        self.image_height = height;
+        self.floats_per_scanline = CHANNELS_PER_PIXEL * width;
+        self.is_hdr = is_hdr;
     }
 
     pub fn width(&self) -> usize {
@@ -83,16 +162,29 @@ impl RtwImage {
         }
     }
 
-    pub fn pixel_data(&self, x: usize, y: usize) -> &[u8] {
-        // 返回坐标为 x,y 的像素的三个字节的地址（如果没有数据，则返回品红色）。
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.is_hdr
+    }
+
+    // 是否有任何像素不是完全不透明的。
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    pub fn pixel_data(&self, x: usize, y: usize) -> &[f32] {
+        // 返回坐标为 x,y 的像素的四个线性 RGBA 分量（如果没有数据，则返回品红色）。
         if self.data.is_empty() {
             &MAGENTA
         } else {
             let x = Self::clamp(x, 0, self.image_width);
             let y = Self::clamp(y, 0, self.image_height);
 
-            &self.data[(y * self.bytes_per_scanline) + (x * BYTES_PER_PIXEL)
-                ..(y * self.bytes_per_scanline) + (x * BYTES_PER_PIXEL) + BYTES_PER_PIXEL]
+            &self.data[(y * self.floats_per_scanline) + (x * CHANNELS_PER_PIXEL)
+                ..(y * self.floats_per_scanline) + (x * CHANNELS_PER_PIXEL) + CHANNELS_PER_PIXEL]
         }
     }
 
@@ -1,3 +1,5 @@
+use super::sampler;
+
 pub const INFINITY: f64 = f64::INFINITY;
 pub const PI: f64 = std::f64::consts::PI;
 
@@ -6,7 +8,7 @@ pub fn degrees_to_radians(degrees: f64) -> f64 {
 }
 
 pub fn random_double() -> f64 {
-    rand::random::<f64>()
+    sampler::next_1d()
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
@@ -0,0 +1,31 @@
+use std::any::Any;
+use std::cell::RefCell;
+
+// 提供 [0, 1) 中随机数的采样器。渲染用到的随机数都来自 rtweekend::random_double，
+// 在线程上安装采样器之后改由它提供，例如 Metropolis 光传输在主样本空间中变异这些数。
+pub trait Sampler: Any {
+    fn next_1d(&mut self) -> f64;
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Box<dyn Sampler>>> = const { RefCell::new(None) };
+}
+
+// 当前线程的下一个随机数。没有安装采样器时是独立的均匀随机数。
+pub fn next_1d() -> f64 {
+    CURRENT.with(|current| match current.borrow_mut().as_mut() {
+        Some(sampler) => sampler.next_1d(),
+        None => rand::random::<f64>(),
+    })
+}
+
+// 在当前线程上安装 sampler 并运行 f，结束后取回采样器，恢复之前的采样器。
+// f 中不能把工作交给其他线程，否则那些线程用的是它们自己的随机数。
+pub fn with_sampler<S: Sampler, R>(sampler: S, f: impl FnOnce() -> R) -> (S, R) {
+    let previous = CURRENT.with(|current| current.replace(Some(Box::new(sampler))));
+    let result = f();
+    let sampler: Box<dyn Any> = CURRENT
+        .with(|current| current.replace(previous))
+        .expect("sampler removed while in use");
+    (*sampler.downcast::<S>().unwrap(), result)
+}
@@ -0,0 +1,371 @@
+use std::sync::OnceLock;
+
+use super::color::{self, Color};
+use super::rtweekend;
+use super::vec3::{self, Vec3};
+
+// 谱渲染的波长范围（纳米），覆盖 CIE 配色函数不为零的部分。
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// RGB 到光谱的系数表每一维的分辨率。
+const TABLE_RES: usize = 24;
+// 拟合时对配色函数积分的步长（纳米）。
+const INTEGRATION_STEP: f64 = 5.0;
+const FIT_ITERATIONS: usize = 50;
+// 系数表覆盖的最大分量的下限。更暗的颜色放大到这个亮度再查表，光谱按比例缩小，
+// 避免在 sigmoid 接近饱和、很难收敛的暗处拟合。
+const DARK: f64 = 0.1;
+
+// 为相机光线均匀采样主波长（hero wavelength）。
+pub fn sample_wavelength() -> f64 {
+    rtweekend::random_double_range(LAMBDA_MIN, LAMBDA_MAX)
+}
+
+// 谱渲染时吞吐量的三个分量对应的波长：主波长，以及把它在波长范围内循环平移
+// 三分之一和三分之二的两个波长。三个波长沿同一条路径传输。
+pub fn wavelengths(hero: f64) -> [f64; 3] {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    [0.0, 1.0, 2.0].map(|k| LAMBDA_MIN + (hero - LAMBDA_MIN + k * range / 3.0) % range)
+}
+
+// 把 RGB 颜色（反射率、纹理、自发光等）上采样为光谱，返回它在主波长为 hero 的三个波长处的值。
+// hero 为 0 时是 RGB 渲染，原样返回。
+pub fn from_rgb(rgb: Color, hero: f64) -> Color {
+    if hero <= 0.0 {
+        return rgb;
+    }
+    let rgb = Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0));
+    let max = rgb.x().max(rgb.y()).max(rgb.z());
+    if max <= 0.0 {
+        return Color::default();
+    }
+
+    // 反射率直接拟合，光谱不超过 1。更亮的颜色（光源）缩放到一半再拟合，光谱按比例放大。
+    let scale = if max < DARK {
+        max / DARK
+    } else if max <= 1.0 {
+        1.0
+    } else {
+        2.0 * max
+    };
+    let coeffs = table().coefficients(rgb / scale);
+    let [l0, l1, l2] = wavelengths(hero);
+    scale
+        * Color::new(
+            sigmoid_spectrum(coeffs, l0),
+            sigmoid_spectrum(coeffs, l1),
+            sigmoid_spectrum(coeffs, l2),
+        )
+}
+
+// 材质为光线选定了主波长（例如色散）之后，另外两个波长的路径不再成立。
+// 只保留主波长，它代替三个波长的平均，所以乘以 3。
+pub fn terminate_secondary(values: Color, hero: f64) -> Color {
+    if hero > 0.0 {
+        Color::new(3.0 * values.x(), 0.0, 0.0)
+    } else {
+        values
+    }
+}
+
+// 把主波长为 hero 的一个样本在三个波长上的辐亮度转换为线性 sRGB，RGB 渲染时原样返回。
+// 结果可能有负的分量，在累加之后才截断。
+pub fn to_linear_srgb(values: Color, hero: f64) -> Color {
+    if hero <= 0.0 {
+        return values;
+    }
+    let mut xyz = Vec3::default();
+    for (k, lambda) in wavelengths(hero).into_iter().enumerate() {
+        xyz += values[k] * color::cie_xyz(lambda);
+    }
+    // 每个波长的概率密度为 1 / (LAMBDA_MAX - LAMBDA_MIN)，三个波长各占三分之一。
+    table().film(xyz * (LAMBDA_MAX - LAMBDA_MIN) / 3.0)
+}
+
+// 预先拟合系数表。系数表在第一次使用时拟合，渲染开始前调用可以避免在渲染线程里等待。
+pub fn initialize() {
+    table();
+}
+
+fn table() -> &'static SigmoidTable {
+    static TABLE: OnceLock<SigmoidTable> = OnceLock::new();
+    TABLE.get_or_init(SigmoidTable::new)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+fn smoothstep(x: f64) -> f64 {
+    x * x * (3.0 - 2.0 * x)
+}
+
+// Jakob 和 Hanika 的光谱模型：在归一化的波长上对二次多项式取 sigmoid，光谱光滑并且位于 [0, 1] 内。
+fn sigmoid_spectrum(coeffs: Vec3, lambda: f64) -> f64 {
+    let t = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN);
+    sigmoid((coeffs.x() * t + coeffs.y()) * t + coeffs.z())
+}
+
+// RGB 到 sigmoid 光谱系数的查找表。按最大的分量分成三张表，每张表以最大分量 z
+// 和另外两个分量与它的比值 x、y 为坐标；z 方向的采样点在两端更密。
+struct SigmoidTable {
+    // 积分用的波长（归一化到 [0, 1]）和配色函数乘以步长。
+    samples: Vec<(f64, Vec3)>,
+    y_integral: f64,
+    // 等能光谱的 XYZ（Y 归一化为 1）和线性 sRGB，胶片除以后者使等能白色显示为白色。
+    white_xyz: Vec3,
+    white: Color,
+    scale: Vec<f64>,
+    coeffs: Vec<Vec3>,
+}
+
+impl SigmoidTable {
+    fn new() -> Self {
+        let steps = ((LAMBDA_MAX - LAMBDA_MIN) / INTEGRATION_STEP) as usize;
+        let samples: Vec<(f64, Vec3)> = (0..=steps)
+            .map(|i| {
+                let lambda = LAMBDA_MIN + INTEGRATION_STEP * i as f64;
+                (
+                    (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN),
+                    INTEGRATION_STEP * color::cie_xyz(lambda),
+                )
+            })
+            .collect();
+        let xyz = samples
+            .iter()
+            .fold(Vec3::default(), |sum, &(_, weight)| sum + weight);
+
+        let mut table = Self {
+            samples,
+            y_integral: xyz.y(),
+            white_xyz: xyz / xyz.y(),
+            white: color::xyz_to_linear_srgb_unclamped(xyz / xyz.y()),
+            scale: (0..TABLE_RES)
+                .map(|i| DARK + (1.0 - DARK) * smoothstep(i as f64 / (TABLE_RES - 1) as f64))
+                .collect(),
+            coeffs: vec![Vec3::default(); 3 * TABLE_RES * TABLE_RES * TABLE_RES],
+        };
+        for l in 0..3 {
+            for j in 0..TABLE_RES {
+                for k in 0..TABLE_RES {
+                    let column = table.fit_column(l, j, k);
+                    for (i, coeffs) in column.into_iter().enumerate() {
+                        table.coeffs[Self::index(l, i, j, k)] = coeffs;
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    fn index(l: usize, i: usize, j: usize, k: usize) -> usize {
+        ((l * TABLE_RES + i) * TABLE_RES + j) * TABLE_RES + k
+    }
+
+    // 积分得到的 XYZ 转换到胶片的线性 sRGB。
+    fn film(&self, xyz: Vec3) -> Color {
+        let c = color::xyz_to_linear_srgb_unclamped(xyz / self.y_integral);
+        Color::new(
+            c.x() / self.white.x(),
+            c.y() / self.white.y(),
+            c.z() / self.white.z(),
+        )
+    }
+
+    // sigmoid 光谱在 CIELAB 中的颜色，白点为等能白色。在 CIELAB 中拟合使暗处的误差和亮处同样重要。
+    fn lab(&self, coeffs: Vec3) -> Vec3 {
+        let xyz = self
+            .samples
+            .iter()
+            .fold(Vec3::default(), |sum, &(t, weight)| {
+                sum + sigmoid((coeffs.x() * t + coeffs.y()) * t + coeffs.z()) * weight
+            });
+        self.xyz_to_lab(xyz / self.y_integral)
+    }
+
+    // 胶片上颜色为 rgb 的光谱在 CIELAB 中的颜色，是 film 的逆变换。
+    fn rgb_to_lab(&self, rgb: Color) -> Vec3 {
+        let c = Color::new(
+            rgb.x() * self.white.x(),
+            rgb.y() * self.white.y(),
+            rgb.z() * self.white.z(),
+        );
+        self.xyz_to_lab(Vec3::new(
+            0.4124 * c.x() + 0.3576 * c.y() + 0.1805 * c.z(),
+            0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z(),
+            0.0193 * c.x() + 0.1192 * c.y() + 0.9505 * c.z(),
+        ))
+    }
+
+    fn xyz_to_lab(&self, xyz: Vec3) -> Vec3 {
+        let f = |t: f64| {
+            const DELTA: f64 = 6.0 / 29.0;
+            if t > DELTA * DELTA * DELTA {
+                t.cbrt()
+            } else {
+                t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+            }
+        };
+        let fx = f(xyz.x() / self.white_xyz.x());
+        let fy = f(xyz.y() / self.white_xyz.y());
+        let fz = f(xyz.z() / self.white_xyz.z());
+        Vec3::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+
+    // 沿 z 方向由暗到亮拟合一列，用前一个格点的解作为初值。
+    fn fit_column(&self, l: usize, j: usize, k: usize) -> Vec<Vec3> {
+        let x = j as f64 / (TABLE_RES - 1) as f64;
+        let y = k as f64 / (TABLE_RES - 1) as f64;
+        let target = |z: f64| {
+            let mut rgb = Color::default();
+            rgb[l] = z;
+            rgb[(l + 1) % 3] = x * z;
+            rgb[(l + 2) % 3] = y * z;
+            rgb
+        };
+
+        let mut coeffs = Vec3::default();
+        self.scale
+            .iter()
+            .map(|&z| {
+                coeffs = self.fit(target(z), coeffs);
+                coeffs
+            })
+            .collect()
+    }
+
+    // 高斯-牛顿迭代求使光谱颜色等于 target 的系数，雅可比矩阵用中心差分近似。
+    // 步长不能减小误差时减半，避免 sigmoid 接近饱和时的大步长发散。
+    fn fit(&self, target: Color, mut coeffs: Vec3) -> Vec3 {
+        let target = self.rgb_to_lab(target);
+        let mut residual = self.lab(coeffs) - target;
+        for _ in 0..FIT_ITERATIONS {
+            if residual.length_squared() < 1e-6 {
+                break;
+            }
+            let derivative = |axis: usize| {
+                let mut delta = Vec3::default();
+                delta[axis] = 1e-4;
+                (self.lab(coeffs + delta) - self.lab(coeffs - delta)) / 2e-4
+            };
+            let (a, b, c) = (derivative(0), derivative(1), derivative(2));
+            let det = vec3::dot(a, vec3::cross(b, c));
+            if det.abs() < 1e-15 {
+                break;
+            }
+            // 克拉默法则解 3x3 线性方程组。
+            let step = Vec3::new(
+                vec3::dot(residual, vec3::cross(b, c)),
+                vec3::dot(a, vec3::cross(residual, c)),
+                vec3::dot(a, vec3::cross(b, residual)),
+            ) / det;
+
+            let mut improved = false;
+            let mut length = 1.0;
+            for _ in 0..20 {
+                let mut candidate = coeffs - length * step;
+                // 无法精确匹配的颜色（例如纯的原色）会使系数发散，限制在 sigmoid 已经饱和的范围内。
+                let max = candidate
+                    .x()
+                    .abs()
+                    .max(candidate.y().abs())
+                    .max(candidate.z().abs());
+                if max > 200.0 {
+                    candidate *= 200.0 / max;
+                }
+                let candidate_residual = self.lab(candidate) - target;
+                if candidate_residual.length_squared() < residual.length_squared() {
+                    coeffs = candidate;
+                    residual = candidate_residual;
+                    improved = true;
+                    break;
+                }
+                length *= 0.5;
+            }
+            if !improved {
+                break;
+            }
+        }
+        coeffs
+    }
+
+    // 查表并三线性插值，rgb 的各分量位于 [0, 1] 内并且不全为零。
+    fn coefficients(&self, rgb: Color) -> Vec3 {
+        let l = if rgb.x() >= rgb.y() && rgb.x() >= rgb.z() {
+            0
+        } else if rgb.y() >= rgb.z() {
+            1
+        } else {
+            2
+        };
+        let z = rgb[l];
+        let x = rgb[(l + 1) % 3] / z;
+        let y = rgb[(l + 2) % 3] / z;
+
+        let i = self
+            .scale
+            .partition_point(|&s| s <= z)
+            .clamp(1, TABLE_RES - 1)
+            - 1;
+        let fz = ((z - self.scale[i]) / (self.scale[i + 1] - self.scale[i])).clamp(0.0, 1.0);
+        let cell = |v: f64| {
+            let g = v * (TABLE_RES - 1) as f64;
+            let index = (g as usize).min(TABLE_RES - 2);
+            (index, g - index as f64)
+        };
+        let (j, fx) = cell(x);
+        let (k, fy) = cell(y);
+
+        let mut coeffs = Vec3::default();
+        for (di, wi) in [(0, 1.0 - fz), (1, fz)] {
+            for (dj, wj) in [(0, 1.0 - fx), (1, fx)] {
+                for (dk, wk) in [(0, 1.0 - fy), (1, fy)] {
+                    coeffs += wi * wj * wk * self.coeffs[Self::index(l, i + di, j + dj, k + dk)];
+                }
+            }
+        }
+        coeffs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEPS: usize = 3000;
+
+    // 在波长范围内分层取主波长，求 from_rgb 得到的光谱转换回线性 sRGB 的平均值。
+    fn round_trip(rgb: Color) -> Color {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut sum = Color::default();
+        for i in 0..STEPS {
+            let hero = LAMBDA_MIN + range * (i as f64 + 0.5) / STEPS as f64;
+            sum += to_linear_srgb(from_rgb(rgb, hero), hero);
+        }
+        sum / STEPS as f64
+    }
+
+    // 系数表拟合失败会让所有谱渲染的结果偏色。除了反射率，也检查很暗的颜色和大于 1 的自发光。
+    #[test]
+    fn from_rgb_round_trips() {
+        for rgb in [
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.5, 0.5, 0.5),
+            Color::new(0.02, 0.03, 0.01),
+            Color::new(4.0, 2.0, 1.0),
+        ] {
+            let back = round_trip(rgb);
+            let max = rgb.x().max(rgb.y()).max(rgb.z());
+            for c in 0..3 {
+                assert!(
+                    (back[c] - rgb[c]).abs() < 0.02 * max,
+                    "{:?} round-trips to {:?}",
+                    rgb,
+                    back
+                );
+            }
+        }
+    }
+}
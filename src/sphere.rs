@@ -1,4 +1,5 @@
-use super::hittable::{HitRecord, Hittable};
+use super::hittable::{self, HitRecord, Hittable};
+use super::hittable_list::HittableList;
 use super::material::Material;
 use super::onb;
 use super::ray::Ray;
@@ -97,6 +98,16 @@ impl<T: Material + Clone + 'static> Hittable for Sphere<T> {
         &self.bbox
     }
 
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() {
+            lights.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn power(&self) -> f64 {
+        4.0 * rtweekend::PI * self.radius * self.radius * self.mat.emitted_power()
+    }
+
     fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
         let mut rec = HitRecord::default();
         if !self.hit(
@@ -121,4 +132,26 @@ impl<T: Material + Clone + 'static> Hittable for Sphere<T> {
         let uvw = onb::Onb::new_from_w(direction);
         uvw.local_v(Self::random_to_sphere(self.radius, distance_squared))
     }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        // 与 random 相同，运动的球体按 time = 0 的位置采样。
+        let outward_normal = vec3::random_unit_vector();
+        rec.t = 0.0;
+        rec.p = self.center.at(0.0) + self.radius * outward_normal;
+        rec.normal = outward_normal;
+        rec.geom_normal = outward_normal;
+        rec.front_face = true;
+        (rec.u, rec.v) = Self::get_sphere_uv(outward_normal);
+        rec.mat = Some(Arc::new(self.mat.clone()) as Arc<dyn Material>);
+        1.0 / (4.0 * rtweekend::PI * self.radius * self.radius)
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        let mut temp_rec = HitRecord::default();
+        if self.hit(r, &hittable::emission_interval(rec.t), &mut temp_rec) {
+            1.0 / (4.0 * rtweekend::PI * self.radius * self.radius)
+        } else {
+            0.0
+        }
+    }
 }
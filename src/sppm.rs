@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rayon::prelude::*;
+
+use super::camera::{Camera, Film};
+use super::color::Color;
+use super::hittable::{HitRecord, Hittable};
+use super::integrator::{self, Integrator, PathTracer, spectral};
+use super::interior::InteriorStack;
+use super::interval::Interval;
+use super::material::{Material, ScatterRecord};
+use super::pdf::{self, HittablePdf, Pdf};
+use super::ray::Ray;
+use super::rtweekend;
+use super::spectrum;
+use super::vec3::{self, Point3};
+
+// 相机光线穿过镜面反射和折射后停下的第一个非镜面的点，光子在它附近被收集。
+struct VisiblePoint {
+    r_in: Ray,
+    rec: HitRecord,
+    mat: Arc<dyn Material>,
+    srec: ScatterRecord,
+    beta: Color,
+}
+
+// 每个像素跨越各遍累积的统计量。
+#[derive(Clone, Copy, Default)]
+struct PixelStats {
+    radius: f64,
+    // 收集到的光子数（按 alpha 折算）。
+    photons: f64,
+    // 收集半径内的光子通量，半径缩小时按面积等比例缩小。
+    tau: Color,
+    direct: Color,
+}
+
+// 随机渐进光子映射（SPPM）。每一遍先从相机追踪到每个像素的可见点并计算直接光照，
+// 再从光源发射光子，用散列网格找出光子附近的可见点，把光子的通量乘以 BSDF 累加到
+// 可见点上。每一遍之后按 alpha 缩小收集半径，估计值随遍数收敛到正确的结果。
+// samples_per_pixel 是遍数，每一遍每个像素一个相机样本。
+// 间接光只来自光子，环境光不发射光子，只提供直接光照。介质中不收集光子，相机光线
+// 在介质中散射后继续前进，直到遇到表面。
+pub struct Sppm {
+    pub photons_per_pass: usize,
+    // 初始的收集半径。为 0 时取可见点处两个像素的宽度。
+    pub initial_radius: f64,
+    // 每一遍保留的新光子的比例，越小半径缩小得越快。
+    pub alpha: f64,
+}
+
+impl Sppm {
+    pub fn new(photons_per_pass: usize) -> Self {
+        Self {
+            photons_per_pass,
+            initial_radius: 0.0,
+            alpha: 2.0 / 3.0,
+        }
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.initial_radius = radius.max(0.0);
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
+    // 沿相机光线找到可见点，返回沿途看到的自发光和可见点处的直接光照。
+    fn visible_point(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+    ) -> (Color, Option<VisiblePoint>) {
+        let mut ray = *r;
+        let mut beta = Color::one();
+        let mut radiance = Color::default();
+        let mut t_start = 0.0;
+        let mut interior = InteriorStack::default();
+
+        let mut depth = 0;
+        while depth < camera.max_depth {
+            let mut rec = HitRecord::default();
+            let ray_t = Interval::new(t_start + 0.001, rtweekend::INFINITY);
+            let hit = world.hit(&ray, &ray_t, &mut rec);
+            beta =
+                beta * integrator::free_flight_weight(camera, &ray, &ray_t, hit.then_some(rec.t));
+            if !hit {
+                radiance += beta * spectral(camera.background_value(ray.direction()), &ray);
+                break;
+            }
+            let Some(mat) = rec.mat.clone() else {
+                break;
+            };
+
+            beta = beta * interior.absorption(&ray, t_start, &rec);
+            if let Some(next) = interior.advance(&ray, &mut rec) {
+                ray = next;
+                t_start = rec.t;
+                continue;
+            }
+
+            // 可见点不再按材质采样，所以沿途和可见点本身的自发光都不需要多重重要性采样的权重。
+            radiance += beta * mat.emitted(&ray, &rec, rec.u, rec.v, rec.p);
+
+            let mut srec = ScatterRecord::default();
+            if !mat.scatter(&ray, &rec, &mut srec) {
+                break;
+            }
+
+            let scattered = if srec.skip_pdf {
+                let mut skip_pdf_ray = srec.skip_pdf_ray;
+                beta = beta * srec.attenuation;
+                if skip_pdf_ray.wavelength() == 0.0 {
+                    skip_pdf_ray = skip_pdf_ray.with_wavelength(ray.wavelength());
+                } else if ray.wavelength() == 0.0 {
+                    beta = spectrum::terminate_secondary(beta, ray.hero_wavelength());
+                }
+                skip_pdf_ray
+            } else if srec.volume {
+                let scattered = Ray::new_with_time(rec.p, srec.pdf.generate(), ray.time())
+                    .with_wavelength(ray.wavelength());
+                let pdf = srec.pdf.value(scattered.direction());
+                if pdf <= 0.0 {
+                    break;
+                }
+                beta = beta * mat.bsdf_cos(&ray, &rec, &srec, &scattered) / pdf;
+                scattered
+            } else {
+                // 直接光照由光源采样和按材质采样的一条光线得到，按幂启发式分配权重；
+                // 后者也照亮没有光源、只有背景的场景。光子从第二次反弹开始才被收集。
+                let direct = |shadow_ray: Ray, pdf: f64, other_pdf: f64| {
+                    let bsdf_cos = mat.bsdf_cos(&ray, &rec, &srec, &shadow_ray);
+                    if pdf <= 0.0 || bsdf_cos.near_zero() {
+                        return Color::default();
+                    }
+                    pdf::power_heuristic(pdf, other_pdf)
+                        * bsdf_cos
+                        * integrator::incident_light(camera, &ray, &shadow_ray, world)
+                        / pdf
+                };
+                let shadow_ray = |direction| {
+                    Ray::new_with_time(rec.p, direction, ray.time())
+                        .with_wavelength(ray.wavelength())
+                        .with_hero_wavelength(ray.hero_wavelength())
+                        .with_interior_priority(interior.priority())
+                };
+                if let Some(lights) = lights {
+                    let light_pdf = HittablePdf::new(Arc::clone(lights), rec.p);
+                    let direction = light_pdf.generate();
+                    radiance += beta
+                        * direct(
+                            shadow_ray(direction),
+                            light_pdf.value(direction),
+                            srec.pdf.value(direction),
+                        );
+                }
+                let direction = srec.pdf.generate();
+                let light_pdf = lights
+                    .as_ref()
+                    .map_or(0.0, |lights| lights.pdf_value(rec.p, direction));
+                radiance +=
+                    beta * direct(shadow_ray(direction), srec.pdf.value(direction), light_pdf);
+                for light in camera.analytic_lights.iter() {
+                    radiance += beta
+                        * integrator::analytic_light(
+                            &ray,
+                            &rec,
+                            &mat,
+                            &srec,
+                            light.as_ref(),
+                            world,
+                        );
+                }
+                return (
+                    radiance,
+                    Some(VisiblePoint {
+                        r_in: ray,
+                        rec,
+                        mat,
+                        srec,
+                        beta,
+                    }),
+                );
+            };
+
+            ray = interior
+                .follow(&rec, scattered)
+                .with_hero_wavelength(ray.hero_wavelength());
+            t_start = 0.0;
+            depth += 1;
+        }
+        (radiance, None)
+    }
+
+    // 从光源发射一个光子，在它经过的非镜面表面上累加到附近的可见点。
+    #[allow(clippy::too_many_arguments)]
+    fn trace_photon(
+        &self,
+        camera: &Camera,
+        world: &Arc<dyn Hittable>,
+        lights: &Arc<dyn Hittable>,
+        hero: f64,
+        grid: &PointGrid,
+        points: &[Option<VisiblePoint>],
+        stats: &[PixelStats],
+        flux: &[Mutex<(Color, u64)>],
+    ) {
+        let mut rec = HitRecord::default();
+        let pdf_pos = lights.sample_emission(&mut rec);
+        if pdf_pos <= 0.0 {
+            return;
+        }
+        let origin = Ray::new_with_time(rec.p, -rec.geom_normal, rtweekend::random_double())
+            .with_hero_wavelength(hero);
+        let direction = integrator::sample_emission_direction(&rec, &origin);
+        let pdf_dir = integrator::emission_direction_pdf(&rec, &origin, direction);
+        let emitted = integrator::emitted_toward(&rec, &origin, direction);
+        if pdf_dir <= 0.0 || emitted.near_zero() {
+            return;
+        }
+        let cos_theta = vec3::dot(direction, integrator::outward_normal(&rec)).abs();
+        let mut beta = emitted * cos_theta / (pdf_pos * pdf_dir);
+        let mut ray =
+            Ray::new_with_time(rec.p, direction, origin.time()).with_hero_wavelength(hero);
+        let mut t_start = 0.0;
+        let mut interior = InteriorStack::default();
+
+        let mut depth = 0;
+        while depth < camera.max_depth {
+            let mut rec = HitRecord::default();
+            let ray_t = Interval::new(t_start + 0.001, rtweekend::INFINITY);
+            if !world.hit(&ray, &ray_t, &mut rec) {
+                break;
+            }
+            let Some(mat) = rec.mat.clone() else {
+                break;
+            };
+
+            beta = beta
+                * interior.absorption(&ray, t_start, &rec)
+                * integrator::free_flight_weight(camera, &ray, &ray_t, Some(rec.t));
+            if let Some(next) = interior.advance(&ray, &mut rec) {
+                ray = next;
+                t_start = rec.t;
+                continue;
+            }
+
+            let mut srec = ScatterRecord::default();
+            let scatters = mat.scatter(&ray, &rec, &mut srec);
+
+            // 第一次命中是直接光照，已经由可见点的光源采样计算。
+            if depth > 0 && !(scatters && (srec.skip_pdf || srec.volume)) {
+                self.deposit(&ray, rec.p, beta, grid, points, stats, flux);
+            }
+            if !scatters {
+                break;
+            }
+
+            let scattered = if srec.skip_pdf {
+                let mut skip_pdf_ray = srec.skip_pdf_ray;
+                beta = beta * srec.attenuation;
+                if skip_pdf_ray.wavelength() == 0.0 {
+                    skip_pdf_ray = skip_pdf_ray.with_wavelength(ray.wavelength());
+                } else if ray.wavelength() == 0.0 {
+                    beta = spectrum::terminate_secondary(beta, ray.hero_wavelength());
+                }
+                skip_pdf_ray
+            } else {
+                let scattered = Ray::new_with_time(rec.p, srec.pdf.generate(), ray.time())
+                    .with_wavelength(ray.wavelength());
+                let pdf = srec.pdf.value(scattered.direction());
+                if pdf <= 0.0 {
+                    break;
+                }
+                let bsdf_cos = mat.bsdf_cos(&ray, &rec, &srec, &scattered);
+                if bsdf_cos.near_zero() {
+                    break;
+                }
+                beta = beta * bsdf_cos / pdf;
+                scattered
+            };
+
+            ray = interior
+                .follow(&rec, scattered)
+                .with_hero_wavelength(ray.hero_wavelength());
+            t_start = 0.0;
+
+            if depth + 1 >= camera.rr_depth {
+                let survive = beta.x().max(beta.y()).max(beta.z()).min(1.0);
+                if rtweekend::random_double() >= survive {
+                    break;
+                }
+                beta /= survive;
+            }
+            depth += 1;
+        }
+    }
+
+    // 沿 photon 方向到达 p 的光子，通量为 beta。
+    #[allow(clippy::too_many_arguments)]
+    fn deposit(
+        &self,
+        photon: &Ray,
+        p: Point3,
+        beta: Color,
+        grid: &PointGrid,
+        points: &[Option<VisiblePoint>],
+        stats: &[PixelStats],
+        flux: &[Mutex<(Color, u64)>],
+    ) {
+        let Some(candidates) = grid.cells.get(&grid.cell(p)) else {
+            return;
+        };
+        for &index in candidates {
+            let Some(vp) = &points[index] else {
+                continue;
+            };
+            let radius = stats[index].radius;
+            if (vp.rec.p - p).length_squared() > radius * radius {
+                continue;
+            }
+            // 光子的通量已经包含了入射的余弦，这里需要的是不含余弦项的 BSDF。
+            let wi = -vec3::unit_vector(photon.direction());
+            let cos_theta = vec3::dot(wi, vp.rec.normal).abs();
+            if cos_theta <= 1e-6 {
+                continue;
+            }
+            let scattered = Ray::new_with_time(vp.rec.p, wi, vp.r_in.time())
+                .with_wavelength(vp.r_in.wavelength());
+            let bsdf_cos = vp.mat.bsdf_cos(&vp.r_in, &vp.rec, &vp.srec, &scattered);
+            let wavelength_factor = integrator::wavelength_factor(&vp.r_in, photon);
+            let contribution = vp.beta * bsdf_cos * beta * (wavelength_factor / cos_theta);
+            let mut flux = flux[index].lock().unwrap();
+            flux.0 += contribution;
+            flux.1 += 1;
+        }
+    }
+}
+
+// 按位置散列的均匀网格。格子的边长为最大的收集半径，每个可见点加入它的收集球覆盖的所有格子，
+// 光子只需要检查所在格子中的可见点。
+struct PointGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl PointGrid {
+    fn new(points: &[Option<VisiblePoint>], stats: &[PixelStats]) -> Self {
+        let cell_size = points
+            .iter()
+            .zip(stats)
+            .filter(|(vp, _)| vp.is_some())
+            .fold(0.0f64, |m, (_, s)| m.max(s.radius))
+            .max(1e-8);
+        let mut grid = Self {
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for (index, (vp, s)) in points.iter().zip(stats).enumerate() {
+            let Some(vp) = vp else {
+                continue;
+            };
+            let offset = Point3::new(s.radius, s.radius, s.radius);
+            let min = grid.cell(vp.rec.p - offset);
+            let max = grid.cell(vp.rec.p + offset);
+            for x in min.0..=max.0 {
+                for y in min.1..=max.1 {
+                    for z in min.2..=max.2 {
+                        grid.cells.entry((x, y, z)).or_default().push(index);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn cell(&self, p: Point3) -> (i64, i64, i64) {
+        (
+            (p.x() / self.cell_size).floor() as i64,
+            (p.y() / self.cell_size).floor() as i64,
+            (p.z() / self.cell_size).floor() as i64,
+        )
+    }
+}
+
+impl Integrator for Sppm {
+    // 单独追踪一条光线时没有光子图可用，退化为路径追踪。
+    fn ray_color(
+        &self,
+        camera: &Camera,
+        r: &Ray,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+        film: &Film,
+    ) -> Color {
+        PathTracer.ray_color(camera, r, world, lights, film)
+    }
+
+    fn render(
+        &self,
+        camera: &Camera,
+        world: &Arc<dyn Hittable>,
+        lights: &Option<Arc<dyn Hittable>>,
+    ) -> Option<Vec<Color>> {
+        let width = camera.image_width;
+        let height = camera.image_height();
+        let passes = camera.samples_per_pixel.max(1);
+        let mut stats = vec![PixelStats::default(); (width * height) as usize];
+
+        let progress = camera.progress_bar(passes as u64);
+        for pass in 0..passes {
+            // 谱渲染时同一遍的相机光线和光子使用同一个主波长。
+            let hero = if camera.spectral {
+                spectrum::sample_wavelength()
+            } else {
+                0.0
+            };
+
+            let points: Vec<(Color, Option<VisiblePoint>)> = (0..width * height)
+                .into_par_iter()
+                .map(|index| {
+                    let r = camera
+                        .sample_ray(index % width, index / width, pass)
+                        .with_hero_wavelength(hero);
+                    self.visible_point(camera, &r, world, lights)
+                })
+                .collect();
+            let (direct, points): (Vec<Color>, Vec<Option<VisiblePoint>>) =
+                points.into_iter().unzip();
+            for (s, (direct, vp)) in stats.iter_mut().zip(direct.iter().zip(&points)) {
+                s.direct += spectrum::to_linear_srgb(*direct, hero);
+                if let (Some(vp), 0.0) = (vp, s.radius) {
+                    s.radius = if self.initial_radius > 0.0 {
+                        self.initial_radius
+                    } else {
+                        2.0 * camera.pixel_footprint(vp.rec.p)
+                    };
+                }
+            }
+
+            if let Some(lights) = lights {
+                let grid = PointGrid::new(&points, &stats);
+                let flux: Vec<Mutex<(Color, u64)>> = (0..points.len())
+                    .map(|_| Mutex::new((Color::default(), 0)))
+                    .collect();
+                (0..self.photons_per_pass).into_par_iter().for_each(|_| {
+                    self.trace_photon(camera, world, lights, hero, &grid, &points, &stats, &flux)
+                });
+
+                // 新收集的光子只保留 alpha 的比例，半径按光子数的增长缩小。
+                for (s, flux) in stats.iter_mut().zip(flux) {
+                    let (phi, m) = flux.into_inner().unwrap();
+                    if m == 0 {
+                        continue;
+                    }
+                    let photons = s.photons + self.alpha * m as f64;
+                    let radius = s.radius * (photons / (s.photons + m as f64)).sqrt();
+                    let shrink = (radius * radius) / (s.radius * s.radius);
+                    s.tau = (s.tau + spectrum::to_linear_srgb(phi, hero)) * shrink;
+                    s.photons = photons;
+                    s.radius = radius;
+                }
+            }
+            progress.inc(1);
+        }
+        progress.finish();
+
+        // 相机把返回值当作 samples_per_pixel 个样本之和。
+        let total_photons = (passes * self.photons_per_pass) as f64;
+        Some(
+            stats
+                .iter()
+                .map(|s| {
+                    let mut radiance = s.direct / passes as f64;
+                    if s.radius > 0.0 {
+                        radiance += s.tau / (total_photons * rtweekend::PI * s.radius * s.radius);
+                    }
+                    radiance * camera.samples_per_pixel as f64
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    const SAMPLES: usize = 4000;
+
+    // 只有背景照明的场景中，可见点的直接光照应当与路径追踪一致。凸的球体上没有间接光照。
+    #[test]
+    fn background_lights_visible_points() {
+        let mut camera = Camera::default();
+        camera.background = Color::new(0.7, 0.8, 1.0);
+        camera.max_depth = 4;
+        let world: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+        ));
+        let sppm = Sppm::new(0);
+        let film = Film::new(1, 1);
+
+        for direction in [Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.3, -1.0, 0.1)] {
+            let r = Ray::new(Point3::new(0.0, 5.0, 0.0), direction);
+            let mut visible = Color::default();
+            let mut traced = Color::default();
+            for _ in 0..SAMPLES {
+                visible += sppm.visible_point(&camera, &r, &world, &None).0;
+                traced += PathTracer.ray_color(&camera, &r, &world, &None, &film);
+            }
+            let visible = visible / SAMPLES as f64;
+            let traced = traced / SAMPLES as f64;
+            assert!(visible.y() > 0.1, "visible point is not lit: {:?}", visible);
+            for c in 0..3 {
+                assert!(
+                    (visible[c] - traced[c]).abs() < 0.05 * traced[c],
+                    "channel {c}: sppm {} path tracer {}",
+                    visible[c],
+                    traced[c]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::color::Color;
+use super::hittable::{HitRecord, Hittable};
+use super::interval::Interval;
+use super::material::{self, Dielectric, Material, ScatterRecord};
+use super::ray::Ray;
+use super::rtweekend;
+use super::spectrum;
+use super::vec3;
+
+// 随机游走的次表面散射（皮肤、蜡、大理石、牛奶）。boundary 必须是封闭的，可以是球体，
+// 也可以是 load_model 加载的网格。表面是光滑的电介质界面，内部是均匀的各向同性散射介质。
+pub struct Subsurface<H: Hittable> {
+    boundary: Arc<H>,
+    interface: Arc<dyn Material>,
+}
+
+impl<H: Hittable + 'static> Subsurface<H> {
+    // albedo 为单次散射反照率，mean_free_path 为每个颜色通道相邻两次散射之间的平均距离。
+    pub fn new(boundary: H, albedo: Color, mean_free_path: Color, ior: f64) -> Self {
+        let boundary = Arc::new(boundary);
+        let chromatic =
+            mean_free_path.x() != mean_free_path.y() || mean_free_path.y() != mean_free_path.z();
+        Self {
+            boundary: Arc::clone(&boundary),
+            interface: Arc::new(SubsurfaceInterface {
+                boundary,
+                dielectric: Dielectric::new(ior),
+                albedo,
+                mean_free_path,
+                chromatic,
+            }),
+        }
+    }
+}
+
+impl<H: Hittable> Hittable for Subsurface<H> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if !self.boundary.hit(r, ray_t, rec) {
+            return false;
+        }
+        rec.mat = Some(Arc::clone(&self.interface));
+        true
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+// 游走的最大散射次数，超过后认为光被吸收。
+const MAX_WALK_STEPS: usize = 256;
+
+// 次表面物体的表面。折射进入内部的光线在这里完成整个随机游走，直到重新折射出表面，
+// 所以内部的散射次数不占用相机的递归深度。
+struct SubsurfaceInterface {
+    boundary: Arc<dyn Hittable>,
+    dielectric: Dielectric,
+    albedo: Color,
+    mean_free_path: Color,
+    chromatic: bool,
+}
+
+impl SubsurfaceInterface {
+    fn mean_free_path(&self, r: &Ray, hero: f64) -> f64 {
+        // 各通道的自由程不同时，光线在进入表面时已经选定了一个通道，谱渲染时选定的是主波长。
+        if hero > 0.0 && r.wavelength() > 0.0 {
+            return spectrum::from_rgb(self.mean_free_path, r.wavelength()).x();
+        }
+        match material::rgb_channel(r.wavelength()) {
+            Some(channel) => self.mean_free_path[channel],
+            None => {
+                (self.mean_free_path.x() + self.mean_free_path.y() + self.mean_free_path.z()) / 3.0
+            }
+        }
+    }
+
+    // 从表面内侧出发的随机游走，返回离开表面的光线和路径上的吞吐量。hero 为谱渲染的主波长。
+    fn random_walk(&self, mut ray: Ray, hero: f64) -> Option<(Ray, Color)> {
+        let mut throughput = Color::one();
+        let mean_free_path = self.mean_free_path(&ray, hero);
+        let albedo = spectrum::from_rgb(self.albedo, hero);
+
+        for _ in 0..MAX_WALK_STEPS {
+            let mut rec = HitRecord::default();
+            if !self
+                .boundary
+                .hit(&ray, &Interval::new(0.001, rtweekend::INFINITY), &mut rec)
+            {
+                // 网格没有封闭时光线会漏出去，直接让它离开。
+                return Some((ray, throughput));
+            }
+
+            let ray_length = ray.direction().length();
+            let hit_distance = -mean_free_path * rtweekend::random_double().ln();
+            if hit_distance < rec.t * ray_length {
+                let p = ray.at(hit_distance / ray_length);
+                throughput = throughput * albedo;
+                ray = Ray::new_with_time(p, vec3::random_unit_vector(), ray.time())
+                    .with_wavelength(ray.wavelength());
+                continue;
+            }
+
+            // 到达边界，按菲涅尔项折射出去或者反射回内部继续游走。
+            let mut srec = ScatterRecord::default();
+            if !self.dielectric.scatter(&ray, &rec, &mut srec) {
+                return None;
+            }
+            throughput = throughput * srec.attenuation;
+            let leaving = vec3::dot(srec.skip_pdf_ray.direction(), rec.normal) < 0.0;
+            ray = srec.skip_pdf_ray;
+            if leaving || rec.front_face {
+                return Some((ray, throughput));
+            }
+        }
+        None
+    }
+}
+
+impl Material for SubsurfaceInterface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        if !self.dielectric.scatter(r_in, rec, srec) {
+            return false;
+        }
+        let entering = rec.front_face && vec3::dot(srec.skip_pdf_ray.direction(), rec.normal) < 0.0;
+        if !entering {
+            return true;
+        }
+
+        let mut ray = srec.skip_pdf_ray;
+        if self.chromatic && ray.wavelength() == 0.0 {
+            let (wavelength, weight) = material::sample_wavelength(r_in);
+            srec.attenuation = srec.attenuation * weight;
+            ray = ray.with_wavelength(wavelength);
+        }
+        match self.random_walk(ray, r_in.hero_wavelength()) {
+            Some((exit_ray, throughput)) => {
+                srec.attenuation = srec.attenuation * throughput;
+                srec.skip_pdf_ray = exit_ray;
+                true
+            }
+            None => false,
+        }
+    }
+}
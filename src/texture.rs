@@ -1,11 +1,67 @@
 use super::color::Color;
 use super::perlin::Perlin;
-use super::rtw_stb_image::RtwImage;
+use super::rtw_stb_image::{ColorSpace, RtwImage};
 use super::vec3::Point3;
-//use std::sync::Arc;
+use std::sync::Arc;
 
-pub trait Texture: Send + Sync + Clone {
+pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+
+    fn alpha(&self, _u: f64, _v: f64, _p: Point3) -> f64 {
+        1.0
+    }
+
+    // 纹理是否处处为黑色。只有能确定时才返回 true，用于判断材质是否发光。
+    fn is_black(&self) -> bool {
+        false
+    }
+
+    // 纹理在整个 (u, v) 范围内的平均颜色，用于估计发光纹理的功率。默认在网格上取样。
+    fn average(&self) -> Color {
+        let n = 8;
+        let mut sum = Color::default();
+        for i in 0..n {
+            for j in 0..n {
+                let u = (i as f64 + 0.5) / n as f64;
+                let v = (j as f64 + 0.5) / n as f64;
+                sum += self.value(u, v, Point3::default());
+            }
+        }
+        sum / (n * n) as f64
+    }
+}
+
+// 共享的纹理对象，用于需要很多个纹理参数的材质，避免为每个参数引入一个泛型。
+impl Texture for Arc<dyn Texture> {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.as_ref().value(u, v, p)
+    }
+
+    fn alpha(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.as_ref().alpha(u, v, p)
+    }
+
+    fn is_black(&self) -> bool {
+        self.as_ref().is_black()
+    }
+
+    fn average(&self) -> Color {
+        self.as_ref().average()
+    }
+}
+
+pub fn constant(c: Color) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(c))
+}
+
+pub fn scalar(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new_with_rgb(value, value, value))
+}
+
+// 标量参数（金属度、粗糙度等）取纹理三个通道的平均值。
+pub fn scalar_value<T: Texture>(texture: &T, u: f64, v: f64, p: Point3) -> f64 {
+    let c = texture.value(u, v, p);
+    (c.x() + c.y() + c.z()) / 3.0
 }
 
 #[derive(Clone)]
@@ -29,6 +85,14 @@ impl Texture for SolidColor {
     fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
         self.color_value
     }
+
+    fn is_black(&self) -> bool {
+        self.color_value.near_zero()
+    }
+
+    fn average(&self) -> Color {
+        self.color_value
+    }
 }
 
 #[derive(Clone)]
@@ -85,6 +149,27 @@ impl ImageTexture {
             image: RtwImage::new(filename),
         }
     }
+
+    pub fn new_with_color_space(filename: &str, color_space: ColorSpace) -> Self {
+        Self {
+            image: RtwImage::new_with_color_space(filename, color_space),
+        }
+    }
+}
+
+impl ImageTexture {
+    pub fn has_alpha(&self) -> bool {
+        self.image.has_alpha()
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> &[f32] {
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+
+        let i = (u * self.image.width() as f64) as usize;
+        let j = (v * self.image.height() as f64) as usize;
+        self.image.pixel_data(i, j)
+    }
 }
 
 impl Texture for ImageTexture {
@@ -93,19 +178,16 @@ impl Texture for ImageTexture {
             return Color::new(0.0, 1.0, 1.0);
         }
 
-        let u = u.clamp(0.0, 1.0);
-        let v = 1.0 - v.clamp(0.0, 1.0);
+        let pixel = self.pixel(u, v);
+        Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64)
+    }
 
-        let i = (u * self.image.width() as f64) as usize;
-        let j = (v * self.image.height() as f64) as usize;
-        let pixel = self.image.pixel_data(i, j);
-
-        let color_scale = 1.0 / 255.0;
-        Color::new(
-            color_scale * pixel[0] as f64,
-            color_scale * pixel[1] as f64,
-            color_scale * pixel[2] as f64,
-        )
+    fn alpha(&self, u: f64, v: f64, _p: Point3) -> f64 {
+        if self.image.height() == 0 {
+            return 1.0;
+        }
+
+        self.pixel(u, v)[3] as f64
     }
 }
 
@@ -1,9 +1,12 @@
 use super::{
     aabb::Aabb,
-    hittable::{HitRecord, Hittable},
+    hittable::{self, HitRecord, Hittable},
+    hittable_list::HittableList,
     interval::Interval,
-    material::Material,
+    material::{self, Material},
+    onb::Onb,
     ray::Ray,
+    rtweekend,
     vec3::{self, Point3, Vec3},
 };
 use std::sync::Arc;
@@ -19,6 +22,12 @@ pub struct Triangle<M: Material> {
     uv0: (f64, f64),
     uv1: (f64, f64),
     uv2: (f64, f64),
+    t0: Vec3,
+    t1: Vec3,
+    t2: Vec3,
+    normal: Vec3,
+    dpdu: Vec3,
+    dpdv: Vec3,
     mat: M,
     bbox: Aabb,
 }
@@ -39,6 +48,14 @@ impl<M: Material> Triangle<M> {
     ) -> Self {
         let mut bbox = Aabb::new_with_point(&p0, &p1);
         bbox = Aabb::new_with_box(&bbox, &Aabb::new_with_point(&p0, &p2));
+
+        // 几何法线的朝向与顶点法线保持一致，这样 front_face 不依赖于顶点的环绕顺序。
+        let mut normal = vec3::unit_vector(vec3::cross(p1 - p0, p2 - p0));
+        if vec3::dot(normal, n0 + n1 + n2) < 0.0 {
+            normal = -normal;
+        }
+        let (dpdu, dpdv) = uv_tangents(p0, p1, p2, uv0, uv1, uv2, normal);
+
         Self {
             p0,
             p1,
@@ -49,21 +66,81 @@ impl<M: Material> Triangle<M> {
             uv0,
             uv1,
             uv2,
+            t0: Vec3::zero(),
+            t1: Vec3::zero(),
+            t2: Vec3::zero(),
+            normal,
+            dpdu,
+            dpdv,
             mat,
             bbox,
         }
     }
+
+    pub fn set_tangents(&mut self, t0: Vec3, t1: Vec3, t2: Vec3) {
+        // 设置顶点切线（通常由模型加载器在共享顶点上平均得到），命中时插值以得到平滑的切线空间。
+        self.t0 = t0;
+        self.t1 = t1;
+        self.t2 = t2;
+    }
 }
 
-impl<M: Material + Clone + 'static> Hittable for Triangle<M> {
-    fn hit(&self, r: &Ray, ray_t: &Interval, hit_record: &mut HitRecord) -> bool {
+pub fn uv_tangents(
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    uv0: (f64, f64),
+    uv1: (f64, f64),
+    uv2: (f64, f64),
+    normal: Vec3,
+) -> (Vec3, Vec3) {
+    // 纹理坐标退化时退回到任意的正交基。
+    uv_derivatives(p0, p1, p2, uv0, uv1, uv2).unwrap_or_else(|| {
+        let uvw = Onb::new_from_w(normal);
+        (uvw.u(), uvw.v())
+    })
+}
+
+// 由三角形的边和纹理坐标差解出 dp/du 和 dp/dv，纹理坐标退化时返回 None。
+pub fn uv_derivatives(
+    p0: Point3,
+    p1: Point3,
+    p2: Point3,
+    uv0: (f64, f64),
+    uv1: (f64, f64),
+    uv2: (f64, f64),
+) -> Option<(Vec3, Vec3)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+    let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+    let det = du1 * dv2 - dv1 * du2;
+
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    Some((
+        (dv2 * edge1 - dv1 * edge2) * inv_det,
+        (du1 * edge2 - du2 * edge1) * inv_det,
+    ))
+}
+
+impl<M: Material> Triangle<M> {
+    fn area(&self) -> f64 {
+        0.5 * vec3::cross(self.p1 - self.p0, self.p2 - self.p0).length()
+    }
+
+    fn intersect(&self, r: &Ray, ray_t: &Interval) -> Option<(f64, f64, f64)> {
+        // Möller–Trumbore 算法，返回 t 和重心坐标 u、v。
         let edge1 = self.p1 - self.p0;
         let edge2 = self.p2 - self.p0;
         let ray_cross_e2 = vec3::cross(r.direction, edge2);
         let det = vec3::dot(edge1, ray_cross_e2);
 
         if det.abs() < 1e-8 {
-            return false;
+            return None;
         }
 
         let inv_det = 1.0 / det;
@@ -71,44 +148,135 @@ impl<M: Material + Clone + 'static> Hittable for Triangle<M> {
         let u = inv_det * vec3::dot(s, ray_cross_e2);
 
         if !(0.0..=1.0).contains(&u) {
-            return false;
+            return None;
         }
 
         let s_cross_e1 = vec3::cross(s, edge1);
         let v = inv_det * vec3::dot(r.direction(), s_cross_e1);
 
         if v < 0.0 || u + v > 1.0 {
-            return false;
+            return None;
         }
 
         let t = inv_det * vec3::dot(edge2, s_cross_e1);
 
         if !ray_t.surrounds(t) {
-            return false;
+            return None;
         }
+        Some((t, u, v))
+    }
+}
 
-        hit_record.t = t;
-        hit_record.p = r.at(t);
+impl<M: Material + Clone + 'static> Triangle<M> {
+    // 在设置了几何法线之后，按重心坐标插值着色法线、切线和纹理坐标，并填入材质。
+    fn interpolate(&self, bary_u: f64, bary_v: f64, hit_record: &mut HitRecord) {
+        let bary_w = 1.0 - bary_u - bary_v;
+
+        let interpolated_normal = bary_w * self.n0 + bary_u * self.n1 + bary_v * self.n2;
+        hit_record.set_shading_normal(vec3::unit_vector(interpolated_normal));
+
+        let interpolated_tangent = bary_w * self.t0 + bary_u * self.t1 + bary_v * self.t2;
+        let dpdu = if interpolated_tangent.near_zero() {
+            self.dpdu
+        } else {
+            vec3::unit_vector(interpolated_tangent) * self.dpdu.length()
+        };
+        hit_record.set_tangents(dpdu, self.dpdv);
+
+        hit_record.u = bary_w * self.uv0.0 + bary_u * self.uv1.0 + bary_v * self.uv2.0;
+        hit_record.v = bary_w * self.uv0.1 + bary_u * self.uv1.1 + bary_v * self.uv2.1;
+
+        hit_record.mat = Some(Arc::new(self.mat.clone()));
+    }
+}
+
+impl<M: Material + Clone + 'static> Hittable for Triangle<M> {
+    fn hit(&self, r: &Ray, ray_t: &Interval, hit_record: &mut HitRecord) -> bool {
+        let Some((t, u, v)) = self.intersect(r, ray_t) else {
+            return false;
+        };
 
         let bary_u = u;
         let bary_v = v;
         let bary_w = 1.0 - bary_u - bary_v;
 
-        let interpolated_normal = bary_w * self.n0 + bary_u * self.n1 + bary_v * self.n2;
-        hit_record.set_face_normal(r, vec3::unit_vector(interpolated_normal));
-
         let interpolated_uv = bary_w * Vec3::new(self.uv0.0, self.uv0.1, 0.0)
             + bary_u * Vec3::new(self.uv1.0, self.uv1.1, 0.0)
             + bary_v * Vec3::new(self.uv2.0, self.uv2.1, 0.0);
+        let p = r.at(t);
 
-        hit_record.u = interpolated_uv.x();
-        hit_record.v = interpolated_uv.y();
+        // 透明贴图在写入命中记录之前测试，被忽略的命中不会影响 BVH 中已有的结果。
+        if !material::alpha_test(self.mat.alpha(interpolated_uv.x(), interpolated_uv.y(), p)) {
+            return false;
+        }
 
-        hit_record.mat = Some(Arc::new(self.mat.clone()));
+        hit_record.t = t;
+        hit_record.p = p;
+
+        hit_record.set_face_normal(r, self.normal);
+        self.interpolate(bary_u, bary_v, hit_record);
 
         true
     }
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        if self.mat.is_emissive() {
+            lights.add(Arc::new(self.clone()));
+        }
+    }
+
+    fn power(&self) -> f64 {
+        self.area() * self.mat.emitted_power()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        // 与 Quad 相同，光源采样不考虑透明贴图。
+        let Some((t, _, _)) = self.intersect(
+            &Ray::new(origin, direction),
+            &Interval::new(0.0001, f64::INFINITY),
+        ) else {
+            return 0.0;
+        };
+
+        let area = self.area();
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (vec3::dot(direction, self.normal) / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: Point3) -> Vec3 {
+        // 在三角形上均匀采样一点。
+        let r1 = rtweekend::random_double().sqrt();
+        let r2 = rtweekend::random_double();
+        let p = (1.0 - r1) * self.p0 + r1 * (1.0 - r2) * self.p1 + r1 * r2 * self.p2;
+        p - origin
+    }
+
+    fn sample_emission(&self, rec: &mut HitRecord) -> f64 {
+        let r1 = rtweekend::random_double().sqrt();
+        let r2 = rtweekend::random_double();
+        let (bary_u, bary_v) = (r1 * (1.0 - r2), r1 * r2);
+        rec.t = 0.0;
+        rec.p = (1.0 - bary_u - bary_v) * self.p0 + bary_u * self.p1 + bary_v * self.p2;
+        rec.normal = self.normal;
+        rec.geom_normal = self.normal;
+        rec.front_face = true;
+        self.interpolate(bary_u, bary_v, rec);
+        1.0 / self.area()
+    }
+
+    fn emission_pdf(&self, r: &Ray, rec: &HitRecord) -> f64 {
+        if self
+            .intersect(r, &hittable::emission_interval(rec.t))
+            .is_some()
+        {
+            1.0 / self.area()
+        } else {
+            0.0
+        }
+    }
 }
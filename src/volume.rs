@@ -0,0 +1,349 @@
+use std::sync::Arc;
+
+use super::aabb::Aabb;
+use super::color::Color;
+use super::constant_medium;
+use super::hittable::{HitRecord, Hittable};
+use super::interval::Interval;
+use super::material::{Anisotropic, Isotropic, Material};
+use super::perlin::Perlin;
+use super::phase::PhaseFunction;
+use super::ray::Ray;
+use super::rtweekend;
+use super::texture::{SolidColor, Texture};
+use super::vec3::{Point3, Vec3};
+
+// 非均匀介质的密度场。max_density 是场的上界，作为追踪时的优势密度（majorant）。
+pub trait Density: Send + Sync {
+    fn density(&self, p: Point3) -> f64;
+    fn max_density(&self) -> f64;
+}
+
+// 三维体素网格，在 [min, max] 范围内按体素中心三线性插值，范围之外密度为零。
+// 数据按 x 最快、z 最慢的顺序排列。
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f32>,
+    min: Point3,
+    max: Point3,
+    max_value: f64,
+}
+
+impl VoxelGrid {
+    // 读取 Mitsuba 的 .vol 网格文件，网格的范围由文件给出。多通道的数据取第一个通道。
+    pub fn new(filename: &str) -> Self {
+        let bytes = std::fs::read(filename)
+            .unwrap_or_else(|_| panic!("ERROR: Could not load volume file \"{}\".", filename));
+        if bytes.len() < 48 || &bytes[0..3] != b"VOL" || bytes[3] != 3 {
+            panic!("ERROR: \"{}\" is not a version 3 .vol file.", filename);
+        }
+        let read_i32 = |i: usize| i32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let read_f32 = |i: usize| f32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        if read_i32(4) != 1 {
+            panic!("ERROR: \"{}\" is not a float32 .vol file.", filename);
+        }
+
+        let (nx, ny, nz) = (
+            read_i32(8) as usize,
+            read_i32(12) as usize,
+            read_i32(16) as usize,
+        );
+        let channels = read_i32(20) as usize;
+        let min = Point3::new(
+            read_f32(24) as f64,
+            read_f32(28) as f64,
+            read_f32(32) as f64,
+        );
+        let max = Point3::new(
+            read_f32(36) as f64,
+            read_f32(40) as f64,
+            read_f32(44) as f64,
+        );
+        let data = (0..nx * ny * nz)
+            .map(|i| read_f32(48 + 4 * i * channels))
+            .collect();
+        Self::new_with_data(nx, ny, nz, data, min, max)
+    }
+
+    // 读取没有文件头的原始数据，每个体素为一个 8 位整数（映射到 [0, 1]）或一个小端 32 位浮点数。
+    pub fn new_with_raw(
+        filename: &str,
+        (nx, ny, nz): (usize, usize, usize),
+        min: Point3,
+        max: Point3,
+    ) -> Self {
+        let bytes = std::fs::read(filename)
+            .unwrap_or_else(|_| panic!("ERROR: Could not load volume file \"{}\".", filename));
+        let count = nx * ny * nz;
+        let data = if bytes.len() == count {
+            bytes.iter().map(|&b| b as f32 / 255.0).collect()
+        } else if bytes.len() == 4 * count {
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect()
+        } else {
+            panic!(
+                "ERROR: \"{}\" does not match a {}x{}x{} grid.",
+                filename, nx, ny, nz
+            );
+        };
+        Self::new_with_data(nx, ny, nz, data, min, max)
+    }
+
+    pub fn new_with_data(
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        data: Vec<f32>,
+        min: Point3,
+        max: Point3,
+    ) -> Self {
+        assert_eq!(data.len(), nx * ny * nz);
+        let max_value = data.iter().fold(0.0f32, |m, &d| m.max(d)) as f64;
+        Self {
+            nx,
+            ny,
+            nz,
+            data,
+            min,
+            max,
+            max_value,
+        }
+    }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f64 {
+        self.data[(k * self.ny + j) * self.nx + i] as f64
+    }
+}
+
+impl Density for VoxelGrid {
+    fn density(&self, p: Point3) -> f64 {
+        let n = [self.nx, self.ny, self.nz];
+        let mut index = [0; 3];
+        let mut frac = [0.0; 3];
+        for a in 0..3 {
+            let t = (p[a] - self.min[a]) / (self.max[a] - self.min[a]);
+            if !(0.0..=1.0).contains(&t) {
+                return 0.0;
+            }
+            // 体素的值位于体素中心，边界上的半个体素沿用最外层的值。
+            let g = (t * n[a] as f64 - 0.5).clamp(0.0, (n[a] - 1) as f64);
+            index[a] = (g as usize).min(n[a].saturating_sub(2));
+            frac[a] = g - index[a] as f64;
+        }
+
+        let mut accum = 0.0;
+        for (di, wi) in [(0, 1.0 - frac[0]), (1, frac[0])] {
+            for (dj, wj) in [(0, 1.0 - frac[1]), (1, frac[1])] {
+                for (dk, wk) in [(0, 1.0 - frac[2]), (1, frac[2])] {
+                    let weight = wi * wj * wk;
+                    if weight > 0.0 {
+                        accum += weight * self.voxel(index[0] + di, index[1] + dj, index[2] + dk);
+                    }
+                }
+            }
+        }
+        accum
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_value
+    }
+}
+
+// 由 Perlin 湍流生成的密度，用于烟雾和云。
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: f64,
+    depth: i32,
+}
+
+impl NoiseDensity {
+    pub fn new(scale: f64) -> Self {
+        Self {
+            noise: Perlin::default(),
+            scale,
+            depth: 7,
+        }
+    }
+
+    pub fn with_depth(mut self, depth: i32) -> Self {
+        self.depth = depth;
+        self
+    }
+}
+
+impl Density for NoiseDensity {
+    fn density(&self, p: Point3) -> f64 {
+        self.noise.turb(self.scale * p, self.depth)
+    }
+
+    fn max_density(&self) -> f64 {
+        // 每一层噪声的绝对值不超过 1，权重依次减半。
+        2.0 * (1.0 - 0.5f64.powi(self.depth))
+    }
+}
+
+// 高度雾：height 以下密度为 1，往上按 falloff 指数衰减。
+pub struct HeightFog {
+    height: f64,
+    falloff: f64,
+}
+
+impl HeightFog {
+    pub fn new(height: f64, falloff: f64) -> Self {
+        Self { height, falloff }
+    }
+}
+
+impl Density for HeightFog {
+    fn density(&self, p: Point3) -> f64 {
+        (-self.falloff * (p.y() - self.height).max(0.0)).exp()
+    }
+
+    fn max_density(&self) -> f64 {
+        1.0
+    }
+}
+
+// 边界内密度为 d * field(p) 的非均匀介质。散射位置用 delta 追踪采样，
+// 阴影光线的透射率用比率追踪估计，两者都以 d * field.max_density() 为优势密度。
+pub struct HeterogeneousMedium<H: Hittable, D: Density, M: Material> {
+    boundary: H,
+    field: D,
+    density: f64,
+    majorant: f64,
+    priority: Option<u32>,
+    phase_function: M,
+}
+
+impl<H: Hittable + 'static, D: Density, T: Texture + 'static>
+    HeterogeneousMedium<H, D, Isotropic<T>>
+{
+    pub fn new(b: H, field: D, d: f64, a: T) -> Self {
+        let majorant = d * field.max_density();
+        Self {
+            boundary: b,
+            field,
+            density: d,
+            majorant,
+            priority: None,
+            phase_function: Isotropic::new(a),
+        }
+    }
+}
+
+impl<H: Hittable + 'static, D: Density> HeterogeneousMedium<H, D, Isotropic<SolidColor>> {
+    pub fn new_with_color(b: H, field: D, d: f64, c: Color) -> Self {
+        let majorant = d * field.max_density();
+        Self {
+            boundary: b,
+            field,
+            density: d,
+            majorant,
+            priority: None,
+            phase_function: Isotropic::new_with_color(c),
+        }
+    }
+}
+
+impl<H: Hittable + 'static, D: Density, T: Texture + 'static, P: PhaseFunction + Clone + 'static>
+    HeterogeneousMedium<H, D, Anisotropic<T, P>>
+{
+    // 按相函数 phase 散射的非均匀介质。
+    pub fn new_with_phase(b: H, field: D, d: f64, a: T, phase: P) -> Self {
+        let majorant = d * field.max_density();
+        Self {
+            boundary: b,
+            field,
+            density: d,
+            majorant,
+            priority: None,
+            phase_function: Anisotropic::new(a, phase),
+        }
+    }
+}
+
+impl<H: Hittable, D: Density, M: Material> HeterogeneousMedium<H, D, M> {
+    // 与 ConstantMedium::with_priority 相同。
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    fn active(&self, r: &Ray) -> bool {
+        self.priority
+            .is_none_or(|priority| r.interior_priority().is_none_or(|top| top <= priority))
+    }
+
+    // 按优势密度采样下一个候选碰撞点，返回 None 表示离开了 inside。
+    fn next_collision(&self, r: &Ray, t: f64, inside: &Interval) -> Option<f64> {
+        let step =
+            -(1.0 - rtweekend::random_double()).ln() / (self.majorant * r.direction().length());
+        let t = t + step;
+        if t >= inside.max { None } else { Some(t) }
+    }
+}
+
+impl<H: Hittable + 'static, D: Density, M: Material + Clone + 'static> Hittable
+    for HeterogeneousMedium<H, D, M>
+{
+    fn hit(&self, r: &Ray, ray_t: &Interval, rec: &mut HitRecord) -> bool {
+        if self.majorant <= 0.0 || !self.active(r) {
+            return false;
+        }
+        let Some(inside) = constant_medium::boundary_interval(&self.boundary, r, ray_t) else {
+            return false;
+        };
+
+        // delta 追踪：候选碰撞点以 密度 / 优势密度 的概率成为真实的散射，否则是虚碰撞，继续前进。
+        let mut t = inside.min;
+        loop {
+            let Some(next) = self.next_collision(r, t, &inside) else {
+                return false;
+            };
+            t = next;
+            let p = r.at(t);
+            if rtweekend::random_double() * self.majorant < self.density * self.field.density(p) {
+                rec.t = t;
+                rec.p = p;
+                rec.normal = Vec3::new(1.0, 0.0, 0.0); // arbitrary
+                rec.front_face = true; // also arbitrary
+                rec.mat = Some(Arc::new(self.phase_function.clone()));
+                return true;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn shadow_hit(&self, _r: &Ray, _ray_t: &Interval, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    fn transmittance(&self, r: &Ray, ray_t: &Interval) -> Color {
+        if self.majorant <= 0.0 || !self.active(r) {
+            return Color::one();
+        }
+        let Some(inside) = constant_medium::boundary_interval(&self.boundary, r, ray_t) else {
+            return Color::one();
+        };
+
+        // 比率追踪：每个候选碰撞点把透射率乘以虚碰撞的概率。
+        let mut transmittance = 1.0;
+        let mut t = inside.min;
+        while let Some(next) = self.next_collision(r, t, &inside) {
+            t = next;
+            transmittance *= 1.0 - self.density * self.field.density(r.at(t)) / self.majorant;
+            if transmittance <= 0.0 {
+                return Color::default();
+            }
+        }
+        Color::one() * transmittance
+    }
+}